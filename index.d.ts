@@ -1,17 +1,128 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
+export interface InvokeOptions {
+  /** Source account as `AccountId` XDR, defaults to an all-zero account. */
+  sourceAccount?: string;
+  /** One of `recording` (default), `enforcing` or `mock_all`. */
+  authMode?: string;
+  /** Whether state changes are written to the ledger, `false` by default. */
+  commit?: boolean;
+}
+
+export interface SimulateTxOptions {
+  /**
+   * One of `enforce`, `record` or `record_allow_nonroot`, defaults to
+   * `enforce` for transactions with authorization entries and to `record`
+   * otherwise.
+   */
+  authMode?: string;
+  /** Instructions added on top of the simulated ones. */
+  instructionLeeway?: number;
+  /** Simulate against a state saved with `snapshot`. */
+  snapshotId?: number;
+  /** Simulate against the state at the end of a ledger of the history. */
+  ledger?: number;
+  /** `LedgerEntry` XDR written for this simulation only. */
+  entries?: Array<string>;
+  /** `LedgerKey` XDR of the entries treated as absent in this simulation. */
+  removedKeys?: Array<string>;
+}
+
 export declare class MarsRover {
   constructor();
   setTime(time: number): void;
   setSequence(seq: number): void;
   getLedgerInfo(): string;
+  getLatestLedger(): string;
+  getLedgers(
+    startLedger?: number | undefined | null,
+    cursor?: string | undefined | null,
+    limit?: number | undefined | null,
+  ): string;
+  setRetentionWindow(ledgers: number): void;
+  getHealth(): string;
+  getVersionInfo(): string;
+  fork(): MarsRover;
+  snapshot(): number;
+  recordCost(label: string): void;
+  compareCostSnapshot(path: string, tolerance?: number | undefined | null): void;
+  writeCostSnapshot(path: string): void;
+  impersonate(address: string): void;
+  stopImpersonating(address: string): void;
   fundAccount(account: string, balance: number): void;
+  setLedgerEntry(entry: string, liveUntil?: number | undefined | null): void;
+  setContractData(
+    contractAddress: string,
+    key: string,
+    val: string,
+    durability: string,
+    liveUntil?: number | undefined | null,
+  ): void;
+  deleteLedgerEntry(key: string): void;
+  setTtl(key: string, liveUntil: number): void;
+  uploadWasm(wasm: Buffer): string;
+  uploadWasmAsync(wasm: Buffer): Promise<string>;
+  deployContract(
+    wasmHash: string,
+    deployer: string,
+    salt?: string | undefined | null,
+    constructorArgs?: Array<string> | undefined | null,
+  ): string;
+  deployContractAsync(
+    wasmHash: string,
+    deployer: string,
+    salt?: string | undefined | null,
+    constructorArgs?: Array<string> | undefined | null,
+  ): Promise<string>;
+  invoke(
+    contractAddress: string,
+    fnName: string,
+    args: Array<string> | string,
+    options?: InvokeOptions | undefined | null,
+  ): string;
+  invokeAsync(
+    contractAddress: string,
+    fnName: string,
+    args: Array<string> | string,
+    options?: InvokeOptions | undefined | null,
+  ): Promise<string>;
   getAccount(account: string): string;
   getBalance(account: string): string;
-  simulateTx(transactionEnvelope: string): string;
-  sendTransaction(transactionEnvelope: string): string;
+  simulateTx(
+    transactionEnvelope: string,
+    options?: SimulateTxOptions | undefined | null,
+  ): string;
+  simulateTxAsync(
+    transactionEnvelope: string,
+    options?: SimulateTxOptions | undefined | null,
+  ): Promise<string>;
+  simulateBatch(
+    transactionEnvelopes: Array<string>,
+    options?: SimulateTxOptions | undefined | null,
+  ): string;
+  simulateBatchAsync(
+    transactionEnvelopes: Array<string>,
+    options?: SimulateTxOptions | undefined | null,
+  ): Promise<string>;
+  setMockAllAuths(enabled: boolean): void;
+  setPrintLogs(enabled: boolean): void;
+  sendTransaction(transactionEnvelope: string, mockAllAuths?: boolean | undefined | null): string;
+  sendTransactionAsync(
+    transactionEnvelope: string,
+    mockAllAuths?: boolean | undefined | null,
+  ): Promise<string>;
   networkPassphrase(): string;
   getNetworkInfo(): string;
   getContractData(contractAddress: string, key: string, durability: string): string;
   getTransaction(hash: string): string;
+  getTransactions(
+    startLedger?: number | undefined | null,
+    cursor?: string | undefined | null,
+    limit?: number | undefined | null,
+  ): string;
+  decodeScVal(value: string): string;
+  encodeScVal(json: string): string;
+  decodeContractEvent(event: string): string;
+  decodeDiagnosticEvent(event: string): string;
+  decodeLedgerEntry(entry: string): string;
 }
@@ -0,0 +1,69 @@
+use mars_rover::rpc::RpcHandler;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8000";
+
+/// Balance given to accounts funded through the friendbot endpoint, in stroops.
+const FRIENDBOT_BALANCE: i64 = 10_000 * 10_000_000;
+
+fn main() -> anyhow::Result<()> {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+
+    let server = Server::http(&address).map_err(|e| anyhow::anyhow!("{e}"))?;
+    let mut handler = RpcHandler::new();
+
+    println!("mars-rover rpc listening on http://{address}/rpc");
+
+    for request in server.incoming_requests() {
+        if let Err(e) = handle_request(&mut handler, request) {
+            eprintln!("failed to send response: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_request(handler: &mut RpcHandler, mut request: Request) -> std::io::Result<()> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    match (request.method(), path) {
+        (Method::Post, "/" | "/rpc") => {
+            let mut body = String::new();
+            if request.as_reader().read_to_string(&mut body).is_err() {
+                return request.respond(Response::empty(400));
+            }
+
+            let response = handler.handle(&body);
+            request.respond(json_response(response, 200))
+        },
+        (Method::Get, "/friendbot") => {
+            let address = query
+                .split('&')
+                .find_map(|param| param.strip_prefix("addr="))
+                .unwrap_or_default();
+
+            let response = match handler.fund_account(address, FRIENDBOT_BALANCE) {
+                Ok(()) => json_response(r#"{"successful":true}"#.into(), 200),
+                Err(e) => json_response(
+                    serde_json::json!({ "successful": false, "detail": format!("{e:#}") })
+                        .to_string(),
+                    400,
+                ),
+            };
+            request.respond(response)
+        },
+        _ => request.respond(Response::empty(404)),
+    }
+}
+
+fn json_response(body: String, status: u16) -> Response<std::io::Cursor<Vec<u8>>> {
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(content_type)
+}
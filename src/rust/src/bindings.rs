@@ -0,0 +1,747 @@
+use std::{
+    path::Path,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde::Serialize;
+use soroban_env_host::xdr::{
+    ContractDataDurability, Hash, Limits, ReadXdr, ScAddress, ScVal, TransactionEnvelope, WriteXdr,
+};
+
+use crate::{
+    ledger_info::NETWORK_PASSPHRASE,
+    model::{
+        AuthMode, Durability, SimulateOptions, SimulationAuthMode, SimulationState, StateOverrides,
+    },
+    sandbox::Sandbox,
+    scval_json::{
+        contract_event_to_json, diagnostic_event_to_json, json_to_scval, ledger_entry_to_json,
+        scval_to_json,
+    },
+};
+
+#[napi(object)]
+pub struct InvokeOptions {
+    /// Source account as `AccountId` XDR, defaults to an all-zero account.
+    pub source_account: Option<String>,
+    /// One of `recording` (default), `enforcing` or `mock_all`.
+    pub auth_mode: Option<String>,
+    /// Whether state changes are written to the ledger, `false` by default.
+    pub commit: Option<bool>,
+}
+
+#[napi(object)]
+pub struct SimulateTxOptions {
+    /// One of `enforce`, `record` or `record_allow_nonroot`, defaults to
+    /// `enforce` for transactions with authorization entries and to `record`
+    /// otherwise.
+    pub auth_mode: Option<String>,
+    /// Instructions added on top of the simulated ones.
+    pub instruction_leeway: Option<u32>,
+    /// Simulate against a state saved with `snapshot`.
+    pub snapshot_id: Option<u32>,
+    /// Simulate against the state at the end of a ledger of the history.
+    pub ledger: Option<u32>,
+    /// `LedgerEntry` XDR written for this simulation only.
+    pub entries: Option<Vec<String>>,
+    /// `LedgerKey` XDR of the entries treated as absent in this simulation.
+    pub removed_keys: Option<Vec<String>>,
+}
+
+#[napi]
+pub struct MarsRover {
+    sandbox: SharedSandbox,
+}
+
+/// Sandbox shared with the worker threads of the async methods. Calls that
+/// change the state take the write lock, so concurrent simulations never see
+/// a half applied transaction.
+#[derive(Clone, Default)]
+struct SharedSandbox(Arc<RwLock<Sandbox>>);
+
+impl SharedSandbox {
+    fn read(&self) -> RwLockReadGuard<'_, Sandbox> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Sandbox> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Sandbox call with its arguments already decoded, so it can run off the
+/// main thread.
+type Job = Box<dyn FnOnce(&SharedSandbox) -> Result<String> + Send>;
+
+/// Runs a job on a libuv worker thread and resolves to its result. Arguments
+/// that failed to decode reject the promise like any other error.
+pub struct SandboxTask {
+    sandbox: SharedSandbox,
+    job: Option<Result<Job>>,
+}
+
+impl Task for SandboxTask {
+    type Output = String;
+    type JsValue = String;
+
+    fn compute(&mut self) -> Result<String> {
+        let job = self
+            .job
+            .take()
+            .ok_or_else(|| Error::from_reason("Task already run"))??;
+
+        job(&self.sandbox)
+    }
+
+    fn resolve(&mut self, _env: Env, output: String) -> Result<String> {
+        Ok(output)
+    }
+}
+
+impl Default for MarsRover {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[napi]
+impl MarsRover {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self {
+            sandbox: SharedSandbox::default(),
+        }
+    }
+
+    #[napi]
+    pub fn set_time(&mut self, time: i64) {
+        self.sandbox.write().set_time(time);
+    }
+
+    #[napi]
+    pub fn set_sequence(&mut self, seq: u32) {
+        self.sandbox.write().set_sequence(seq);
+    }
+
+    #[napi]
+    pub fn get_ledger_info(&self) -> Result<String> {
+        let info: crate::model::LedgerInfo = self.sandbox.read().get_ledger_info().clone().into();
+
+        to_json(&info)
+    }
+
+    #[napi]
+    pub fn get_latest_ledger(&self) -> Result<String> {
+        to_json(&self.sandbox.read().get_latest_ledger())
+    }
+
+    #[napi]
+    pub fn get_ledgers(
+        &self,
+        start_ledger: Option<u32>,
+        cursor: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<String> {
+        let response = self
+            .sandbox
+            .read()
+            .get_ledgers(start_ledger, cursor, limit)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&response)
+    }
+
+    #[napi]
+    pub fn set_retention_window(&mut self, ledgers: u32) {
+        self.sandbox.write().set_retention_window(ledgers);
+    }
+
+    #[napi]
+    pub fn get_health(&self) -> Result<String> {
+        to_json(&self.sandbox.read().get_health())
+    }
+
+    #[napi]
+    pub fn get_version_info(&self) -> Result<String> {
+        to_json(&self.sandbox.read().get_version_info())
+    }
+
+    #[napi]
+    pub fn fork(&self) -> MarsRover {
+        MarsRover {
+            sandbox: SharedSandbox(Arc::new(RwLock::new(self.sandbox.read().fork()))),
+        }
+    }
+
+    #[napi]
+    pub fn snapshot(&mut self) -> u32 {
+        self.sandbox.write().snapshot()
+    }
+
+    #[napi]
+    pub fn record_cost(&mut self, label: String) -> Result<()> {
+        self.sandbox
+            .write()
+            .record_cost(&label)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn compare_cost_snapshot(&self, path: String, tolerance: Option<f64>) -> Result<()> {
+        self.sandbox
+            .read()
+            .compare_cost_snapshot(Path::new(&path), tolerance.unwrap_or(0.0))
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn write_cost_snapshot(&self, path: String) -> Result<()> {
+        self.sandbox
+            .read()
+            .write_cost_snapshot(Path::new(&path))
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn impersonate(&mut self, address: String) -> Result<()> {
+        self.sandbox
+            .write()
+            .impersonate(from_xdr(&address, "address")?);
+
+        Ok(())
+    }
+
+    #[napi]
+    pub fn stop_impersonating(&mut self, address: String) -> Result<()> {
+        self.sandbox
+            .write()
+            .stop_impersonating(&from_xdr(&address, "address")?);
+
+        Ok(())
+    }
+
+    #[napi]
+    pub fn fund_account(&self, account: String, balance: i64) -> Result<()> {
+        self.sandbox
+            .write()
+            .fund_account(from_xdr(&account, "account")?, balance)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn set_ledger_entry(&self, entry: String, live_until: Option<u32>) -> Result<()> {
+        self.sandbox
+            .write()
+            .set_ledger_entry(from_xdr(&entry, "ledger entry")?, live_until)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn set_contract_data(
+        &self,
+        contract_address: String,
+        key: String,
+        val: String,
+        durability: String,
+        live_until: Option<u32>,
+    ) -> Result<()> {
+        self.sandbox
+            .write()
+            .set_contract_data(
+                from_xdr(&contract_address, "contract address")?,
+                from_xdr(&key, "key")?,
+                from_xdr(&val, "value")?,
+                parse_durability(&durability)?,
+                live_until,
+            )
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn delete_ledger_entry(&self, key: String) -> Result<()> {
+        self.sandbox
+            .write()
+            .delete_ledger_entry(from_xdr(&key, "key")?)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn set_ttl(&self, key: String, live_until: u32) -> Result<()> {
+        self.sandbox
+            .write()
+            .set_ttl(from_xdr(&key, "key")?, live_until)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn upload_wasm(&self, wasm: Buffer) -> Result<String> {
+        upload_wasm_job(wasm.to_vec())(&self.sandbox)
+    }
+
+    #[napi]
+    pub fn upload_wasm_async(&self, wasm: Buffer) -> AsyncTask<SandboxTask> {
+        self.task(Ok(upload_wasm_job(wasm.to_vec())))
+    }
+
+    #[napi]
+    pub fn deploy_contract(
+        &self,
+        wasm_hash: String,
+        deployer: String,
+        salt: Option<String>,
+        constructor_args: Option<Vec<String>>,
+    ) -> Result<String> {
+        deploy_contract_job(wasm_hash, deployer, salt, constructor_args)?(&self.sandbox)
+    }
+
+    #[napi]
+    pub fn deploy_contract_async(
+        &self,
+        wasm_hash: String,
+        deployer: String,
+        salt: Option<String>,
+        constructor_args: Option<Vec<String>>,
+    ) -> AsyncTask<SandboxTask> {
+        self.task(deploy_contract_job(
+            wasm_hash,
+            deployer,
+            salt,
+            constructor_args,
+        ))
+    }
+
+    #[napi]
+    pub fn invoke(
+        &self,
+        contract_address: String,
+        fn_name: String,
+        args: Either<Vec<String>, String>,
+        options: Option<InvokeOptions>,
+    ) -> Result<String> {
+        invoke_job(contract_address, fn_name, args, options)?(&self.sandbox)
+    }
+
+    #[napi]
+    pub fn invoke_async(
+        &self,
+        contract_address: String,
+        fn_name: String,
+        args: Either<Vec<String>, String>,
+        options: Option<InvokeOptions>,
+    ) -> AsyncTask<SandboxTask> {
+        self.task(invoke_job(contract_address, fn_name, args, options))
+    }
+
+    #[napi]
+    pub fn get_account(&self, account: String) -> Result<String> {
+        let account = self
+            .sandbox
+            .read()
+            .get_account(from_xdr(&account, "account")?)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&account)
+    }
+
+    #[napi]
+    pub fn get_balance(&self, account: String) -> Result<String> {
+        self.sandbox
+            .read()
+            .get_balance(from_xdr(&account, "account")?)
+            .map(|balance| balance.to_string())
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn simulate_tx(
+        &self,
+        transaction_envelope: String,
+        options: Option<SimulateTxOptions>,
+    ) -> Result<String> {
+        simulate_tx_job(transaction_envelope, options)?(&self.sandbox)
+    }
+
+    #[napi]
+    pub fn simulate_tx_async(
+        &self,
+        transaction_envelope: String,
+        options: Option<SimulateTxOptions>,
+    ) -> AsyncTask<SandboxTask> {
+        self.task(simulate_tx_job(transaction_envelope, options))
+    }
+
+    #[napi]
+    pub fn simulate_batch(
+        &self,
+        transaction_envelopes: Vec<String>,
+        options: Option<SimulateTxOptions>,
+    ) -> Result<String> {
+        simulate_batch_job(transaction_envelopes, options)?(&self.sandbox)
+    }
+
+    #[napi]
+    pub fn simulate_batch_async(
+        &self,
+        transaction_envelopes: Vec<String>,
+        options: Option<SimulateTxOptions>,
+    ) -> AsyncTask<SandboxTask> {
+        self.task(simulate_batch_job(transaction_envelopes, options))
+    }
+
+    #[napi]
+    pub fn set_mock_all_auths(&mut self, enabled: bool) {
+        self.sandbox.write().set_mock_all_auths(enabled);
+    }
+
+    #[napi]
+    pub fn set_print_logs(&mut self, enabled: bool) {
+        self.sandbox.write().set_print_logs(enabled);
+    }
+
+    #[napi]
+    pub fn send_transaction(
+        &mut self,
+        transaction_envelope: String,
+        mock_all_auths: Option<bool>,
+    ) -> Result<String> {
+        send_transaction_job(transaction_envelope, mock_all_auths)?(&self.sandbox)
+    }
+
+    #[napi]
+    pub fn send_transaction_async(
+        &self,
+        transaction_envelope: String,
+        mock_all_auths: Option<bool>,
+    ) -> AsyncTask<SandboxTask> {
+        self.task(send_transaction_job(transaction_envelope, mock_all_auths))
+    }
+
+    #[napi]
+    pub fn network_passphrase(&self) -> String {
+        NETWORK_PASSPHRASE.to_string()
+    }
+
+    #[napi]
+    pub fn get_network_info(&self) -> Result<String> {
+        to_json(&self.sandbox.read().get_network_info())
+    }
+
+    #[napi]
+    pub fn get_contract_data(
+        &self,
+        contract_address: String,
+        key: String,
+        durability: String,
+    ) -> Result<String> {
+        let response = self
+            .sandbox
+            .read()
+            .get_contract_data(
+                from_xdr(&contract_address, "contract address")?,
+                from_xdr(&key, "key")?,
+                parse_durability(&durability)?,
+            )
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&response)
+    }
+
+    #[napi]
+    pub fn get_transaction(&self, hash: String) -> Result<String> {
+        let response = self
+            .sandbox
+            .read()
+            .get_transaction(hash)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&response)
+    }
+
+    #[napi]
+    pub fn get_transactions(
+        &self,
+        start_ledger: Option<u32>,
+        cursor: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<String> {
+        let response = self
+            .sandbox
+            .read()
+            .get_transactions(start_ledger, cursor, limit)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&response)
+    }
+
+    #[napi]
+    pub fn decode_sc_val(&self, value: String) -> Result<String> {
+        to_json(&scval_to_json(&from_xdr(&value, "value")?))
+    }
+
+    #[napi]
+    pub fn encode_sc_val(&self, json: String) -> Result<String> {
+        let json = serde_json::from_str(&json)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON: {e}")))?;
+        let value = json_to_scval(&json).map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_xdr(&value)
+    }
+
+    #[napi]
+    pub fn decode_contract_event(&self, event: String) -> Result<String> {
+        to_json(&contract_event_to_json(&from_xdr(
+            &event,
+            "contract event",
+        )?))
+    }
+
+    #[napi]
+    pub fn decode_diagnostic_event(&self, event: String) -> Result<String> {
+        to_json(&diagnostic_event_to_json(&from_xdr(
+            &event,
+            "diagnostic event",
+        )?))
+    }
+
+    #[napi]
+    pub fn decode_ledger_entry(&self, entry: String) -> Result<String> {
+        let json = ledger_entry_to_json(&from_xdr(&entry, "ledger entry")?)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&json)
+    }
+}
+
+impl MarsRover {
+    fn task(&self, job: Result<Job>) -> AsyncTask<SandboxTask> {
+        AsyncTask::new(SandboxTask {
+            sandbox: self.sandbox.clone(),
+            job: Some(job),
+        })
+    }
+}
+
+fn upload_wasm_job(wasm: Vec<u8>) -> Job {
+    Box::new(move |sandbox| {
+        sandbox
+            .write()
+            .upload_wasm(wasm)
+            .map(|hash| hex::encode(hash.0))
+            .map_err(|e| Error::from_reason(e.to_string()))
+    })
+}
+
+fn deploy_contract_job(
+    wasm_hash: String,
+    deployer: String,
+    salt: Option<String>,
+    constructor_args: Option<Vec<String>>,
+) -> Result<Job> {
+    let wasm_hash = Hash(from_hex(&wasm_hash, "wasm hash")?);
+    let deployer = from_xdr(&deployer, "deployer address")?;
+    let salt = salt.map(|salt| from_hex(&salt, "salt")).transpose()?;
+    let constructor_args = constructor_args
+        .unwrap_or_default()
+        .iter()
+        .map(|arg| from_xdr(arg, "constructor argument"))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Box::new(move |sandbox| {
+        let address = sandbox
+            .write()
+            .deploy_contract(wasm_hash, deployer, salt, constructor_args)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_xdr(&address)
+    }))
+}
+
+fn invoke_job(
+    contract_address: String,
+    fn_name: String,
+    args: Either<Vec<String>, String>,
+    options: Option<InvokeOptions>,
+) -> Result<Job> {
+    let options = options.unwrap_or(InvokeOptions {
+        source_account: None,
+        auth_mode: None,
+        commit: None,
+    });
+    let auth_mode = options
+        .auth_mode
+        .map(|mode| mode.parse::<AuthMode>())
+        .transpose()
+        .map_err(|e| Error::from_reason(e.to_string()))?
+        .unwrap_or_default();
+    let contract_address: ScAddress = from_xdr(&contract_address, "contract address")?;
+    let args = match args {
+        Either::A(args) => Either::A(
+            args.iter()
+                .map(|arg| from_xdr(arg, "argument"))
+                .collect::<Result<Vec<ScVal>>>()?,
+        ),
+        Either::B(json) => Either::B(
+            serde_json::from_str::<serde_json::Value>(&json)
+                .map_err(|e| Error::from_reason(format!("Invalid JSON arguments: {e}")))?,
+        ),
+    };
+    let source_account = options
+        .source_account
+        .map(|account| from_xdr(&account, "source account"))
+        .transpose()?;
+    let commit = options.commit.unwrap_or(false);
+
+    let invoke = move |sandbox: &Sandbox| {
+        // JSON arguments are converted with the spec of the contract.
+        let args = match args {
+            Either::A(args) => args,
+            Either::B(json) => sandbox
+                .args_from_json(&contract_address, &fn_name, &json)
+                .map_err(|e| Error::from_reason(e.to_string()))?,
+        };
+
+        let response = sandbox
+            .invoke(
+                contract_address,
+                &fn_name,
+                args,
+                source_account,
+                auth_mode,
+                commit,
+            )
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&response)
+    };
+
+    // Invocations that don't commit only read the state.
+    Ok(Box::new(move |sandbox| match commit {
+        true => invoke(&sandbox.write()),
+        false => invoke(&sandbox.read()),
+    }))
+}
+
+fn simulate_tx_job(
+    transaction_envelope: String,
+    options: Option<SimulateTxOptions>,
+) -> Result<Job> {
+    let transaction_envelope: TransactionEnvelope =
+        from_xdr(&transaction_envelope, "transaction envelope")?;
+    let options = simulate_options(options)?;
+
+    Ok(Box::new(move |sandbox| {
+        let response = sandbox
+            .read()
+            .simulate_transaction(transaction_envelope, options)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&response)
+    }))
+}
+
+fn simulate_batch_job(
+    transaction_envelopes: Vec<String>,
+    options: Option<SimulateTxOptions>,
+) -> Result<Job> {
+    let transaction_envelopes = transaction_envelopes
+        .iter()
+        .map(|envelope| from_xdr(envelope, "transaction envelope"))
+        .collect::<Result<Vec<TransactionEnvelope>>>()?;
+    let options = simulate_options(options)?;
+
+    Ok(Box::new(move |sandbox| {
+        let responses = sandbox
+            .read()
+            .simulate_batch(transaction_envelopes, options)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&responses)
+    }))
+}
+
+fn simulate_options(options: Option<SimulateTxOptions>) -> Result<SimulateOptions> {
+    Ok(match options {
+        Some(options) => SimulateOptions {
+            auth_mode: options
+                .auth_mode
+                .map(|mode| mode.parse::<SimulationAuthMode>())
+                .transpose()
+                .map_err(|e| Error::from_reason(e.to_string()))?,
+            instruction_leeway: options.instruction_leeway,
+            state: match (options.snapshot_id, options.ledger) {
+                (None, None) => SimulationState::Latest,
+                (Some(id), None) => SimulationState::Snapshot(id),
+                (None, Some(ledger)) => SimulationState::Ledger(ledger),
+                (Some(_), Some(_)) => {
+                    return Err(Error::from_reason(
+                        "Only one of snapshotId and ledger can be set",
+                    ))
+                },
+            },
+            overrides: (options.entries.is_some() || options.removed_keys.is_some())
+                .then(|| -> Result<_> {
+                    Ok(StateOverrides {
+                        entries: options
+                            .entries
+                            .unwrap_or_default()
+                            .iter()
+                            .map(|entry| Ok((from_xdr(entry, "ledger entry")?, None)))
+                            .collect::<Result<_>>()?,
+                        removed: options
+                            .removed_keys
+                            .unwrap_or_default()
+                            .iter()
+                            .map(|key| from_xdr(key, "ledger key"))
+                            .collect::<Result<_>>()?,
+                    })
+                })
+                .transpose()?,
+        },
+        None => SimulateOptions::default(),
+    })
+}
+
+fn send_transaction_job(transaction_envelope: String, mock_all_auths: Option<bool>) -> Result<Job> {
+    let transaction_envelope: TransactionEnvelope =
+        from_xdr(&transaction_envelope, "transaction envelope")?;
+
+    Ok(Box::new(move |sandbox| {
+        let response = sandbox
+            .write()
+            .send_transaction(transaction_envelope, mock_all_auths)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        to_json(&response)
+    }))
+}
+
+fn from_xdr<T: ReadXdr>(value: &str, name: &str) -> Result<T> {
+    T::from_xdr_base64(value, Limits::none())
+        .map_err(|e| Error::from_reason(format!("Invalid {name} XDR: {e}")))
+}
+
+fn to_xdr<T: WriteXdr>(value: &T) -> Result<String> {
+    value
+        .to_xdr_base64(Limits::none())
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| Error::from_reason(e.to_string()))
+}
+
+fn from_hex(value: &str, name: &str) -> Result<[u8; 32]> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::from_reason(format!("{name} must be 32 bytes hex encoded")))
+}
+
+fn parse_durability(durability: &str) -> Result<ContractDataDurability> {
+    durability
+        .parse::<Durability>()
+        .map(Into::into)
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
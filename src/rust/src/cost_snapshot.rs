@@ -0,0 +1,160 @@
+//! Resource usage of labelled transactions, kept in a snapshot file to catch
+//! cost regressions like `forge snapshot`.
+
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Resources used by a sent transaction or an invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostRecord {
+    pub instructions: u64,
+    pub read_bytes: u32,
+    pub write_bytes: u32,
+    /// Resource fee of the resources used, not the one declared.
+    pub fee: i64,
+}
+
+impl CostRecord {
+    fn metrics(&self) -> [(&'static str, i128); 4] {
+        [
+            ("instructions", self.instructions.into()),
+            ("readBytes", self.read_bytes.into()),
+            ("writeBytes", self.write_bytes.into()),
+            ("fee", self.fee.into()),
+        ]
+    }
+}
+
+/// Difference between the recorded costs and a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CostDeviation {
+    /// Label recorded but missing from the snapshot.
+    Added { label: String },
+    /// Label of the snapshot that wasn't recorded.
+    Removed { label: String },
+    /// Metric of a label that moved by more than the tolerance.
+    Changed {
+        label: String,
+        metric: &'static str,
+        expected: i128,
+        actual: i128,
+    },
+}
+
+impl fmt::Display for CostDeviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { label } => write!(f, "{label}: not in the snapshot"),
+            Self::Removed { label } => write!(f, "{label}: not recorded"),
+            Self::Changed {
+                label,
+                metric,
+                expected,
+                actual,
+            } => {
+                write!(f, "{label}: {metric} {expected} -> {actual}")?;
+                if *expected != 0 {
+                    let change = (actual - expected) as f64 / *expected as f64 * 100.0;
+                    write!(f, " ({change:+.2}%)")?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Costs by label, written as a JSON object sorted by label.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CostSnapshot {
+    costs: BTreeMap<String, CostRecord>,
+}
+
+impl CostSnapshot {
+    pub fn record(&mut self, label: &str, cost: CostRecord) {
+        self.costs.insert(label.to_string(), cost);
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cost snapshot {}", path.display()))?;
+
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid cost snapshot {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)? + "\n";
+
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write cost snapshot {}", path.display()))
+    }
+
+    /// Labels recorded in only one of the snapshots, and metrics which differ
+    /// from the expected ones by more than `tolerance` percent.
+    pub fn compare(&self, expected: &CostSnapshot, tolerance: f64) -> Vec<CostDeviation> {
+        let mut deviations = vec![];
+        for (label, cost) in &self.costs {
+            let Some(expected) = expected.costs.get(label) else {
+                deviations.push(CostDeviation::Added {
+                    label: label.clone(),
+                });
+                continue;
+            };
+
+            for ((metric, expected), (_, actual)) in
+                expected.metrics().into_iter().zip(cost.metrics())
+            {
+                let allowed = expected.abs() as f64 * tolerance / 100.0;
+                if (actual - expected).abs() as f64 > allowed {
+                    deviations.push(CostDeviation::Changed {
+                        label: label.clone(),
+                        metric,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+        for label in expected.costs.keys() {
+            if !self.costs.contains_key(label) {
+                deviations.push(CostDeviation::Removed {
+                    label: label.clone(),
+                });
+            }
+        }
+
+        deviations
+    }
+
+    /// Compares the recorded costs with the snapshot file, which is only
+    /// ever written by [`CostSnapshot::write`]. Fails listing every added or
+    /// removed label and every metric that moved by more than `tolerance`
+    /// percent.
+    pub fn check(&self, path: &Path, tolerance: f64) -> Result<()> {
+        ensure!(tolerance >= 0.0, "Tolerance can't be negative");
+        ensure!(
+            path.exists(),
+            "Cost snapshot {} doesn't exist, write it with writeCostSnapshot",
+            path.display()
+        );
+
+        let deviations = self.compare(&Self::read(path)?, tolerance);
+        ensure!(
+            deviations.is_empty(),
+            "Costs deviate from {}:\n{}",
+            path.display(),
+            deviations
+                .iter()
+                .map(|deviation| deviation.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+
+        Ok(())
+    }
+}
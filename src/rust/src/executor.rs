@@ -1,16 +1,26 @@
-use std::{collections::HashSet, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashSet},
+    rc::Rc,
+    sync::Arc,
+};
 
 use anyhow::{ensure, Context, Result};
 use soroban_env_host::{
     budget::Budget,
-    e2e_invoke::{self, InvokeHostFunctionResult, LedgerEntryChange, RecordingInvocationAuthMode},
+    e2e_invoke::{
+        self, InvokeHostFunctionRecordingModeResult, InvokeHostFunctionResult, LedgerEntryChange,
+        RecordingInvocationAuthMode,
+    },
     storage::SnapshotSource,
     xdr::{
-        AccountId, ContractCostParamEntry, ContractCostParams, ContractEvent, DiagnosticEvent,
-        ExtensionPoint, HostFunction, LedgerEntry, LedgerEntryData, LedgerKey,
-        LedgerKeyContractCode, LedgerKeyContractData, Limits, OperationBody, ReadXdr,
-        SorobanAuthorizationEntry, SorobanResources, SorobanTransactionDataExt, TransactionExt,
-        TransactionResultResult, TransactionV1Envelope, WriteXdr,
+        AccountId, ContractCostType, ContractEvent, DecoratedSignature, DiagnosticEvent,
+        HostFunction, InvokeHostFunctionOp, LedgerEntry, LedgerEntryData, LedgerKey,
+        LedgerKeyContractCode, LedgerKeyContractData, Limits, Memo, MuxedAccount, Operation,
+        OperationBody, Preconditions, ReadXdr, ScAddress, SequenceNumber, Signature, SignatureHint,
+        SorobanAuthorizationEntry, SorobanCredentials, SorobanResources, SorobanResourcesExtV0,
+        SorobanTransactionData, SorobanTransactionDataExt, Transaction, TransactionEnvelope,
+        TransactionExt, TransactionResultResult, TransactionV1Envelope, Uint256, WriteXdr,
     },
     HostError, LedgerInfo,
 };
@@ -19,89 +29,212 @@ use soroban_simulation::simulation::{
 };
 
 use crate::{
+    impersonation::{check_access, is_nonce, verify_unmocked, DeclaredFootprint},
     memory::Memory,
     model::{
-        SimulateHostFunctionResult, SimulateTransactionErrorResponse, SimulateTransactionResponse,
-        SimulateTransactionSuccessResponse,
+        CostProfile, CostTypeUsage, FootprintDiagnostics, SimulateHostFunctionResult,
+        SimulateOptions, SimulateTransactionErrorResponse, SimulateTransactionResponse,
+        SimulateTransactionSuccessResponse, SimulationAuthMode, SimulationCost,
     },
+    module_cache::ModuleStore,
     network_config::default_network_config,
-    utils::{build_module_cache_for_entries, changes_from_simulation, failed_result, ttl_entry},
+    simulation::resource_fee,
+    spec::SpecIndex,
+    trace::{replay_frame_costs, FrameCost, FrameCostRecorder, InvocationTrace},
+    utils::{changes_from_simulation, failed_result, footprint_diagnostics, ttl_entry},
 };
 
 pub struct ExecutionResult {
     pub error: Option<TransactionResultResult>,
     pub fee_charges: i64,
+    /// Resource fee of the resources the invocation used.
+    pub resource_fee: i64,
     pub result: Result<Vec<u8>, HostError>,
+    /// Contract events.
     pub events: Vec<DiagnosticEvent>,
+    /// All the events recorded by the host, contract logs and error context
+    /// included.
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+    /// Authorizations the invocation required, when some were mocked.
+    pub required_auth: Option<Vec<SorobanAuthorizationEntry>>,
+    /// Footprint mismatches of a failed invocation.
+    pub footprint_diagnostics: Option<FootprintDiagnostics>,
+    pub trace: InvocationTrace,
+    pub profile: CostProfile,
+}
+
+/// Relaxes how `require_auth` is checked for a sent transaction.
+pub enum AuthOverride<'a> {
+    /// Authorization entries are enforced as submitted.
+    None,
+    /// Every `require_auth` succeeds.
+    MockAll,
+    /// `require_auth` succeeds for the given addresses, the others need a
+    /// matching authorization entry in the transaction.
+    Impersonate(&'a BTreeSet<ScAddress>),
+}
+
+impl AuthOverride<'_> {
+    /// Whether `require_auth` of the address is mocked.
+    pub fn mocks(&self, address: &ScAddress) -> bool {
+        match self {
+            AuthOverride::None => false,
+            AuthOverride::MockAll => true,
+            AuthOverride::Impersonate(addresses) => addresses.contains(address),
+        }
+    }
+}
+
+pub struct RecordingExecutionResult {
+    pub result: InvokeHostFunctionRecordingModeResult,
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+    pub cpu_insns: u64,
+    pub mem_bytes: u64,
+    pub budget: Budget,
+}
+
+pub struct HostFunctionExecution {
+    pub result: InvokeHostFunctionResult,
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+    /// Budget spent by each contract call, in call order.
+    pub frame_costs: Vec<FrameCost>,
+    pub budget: Budget,
 }
 
 pub struct Executor {
-    memory: Rc<Memory>,
+    memory: Arc<Memory>,
+    specs: Arc<SpecIndex>,
+    modules: Arc<ModuleStore>,
 }
 
 impl Executor {
-    pub fn new(memory: Rc<Memory>) -> Self {
-        Self { memory }
+    pub fn new(memory: Arc<Memory>, specs: Arc<SpecIndex>, modules: Arc<ModuleStore>) -> Self {
+        Self {
+            memory,
+            specs,
+            modules,
+        }
     }
 
     pub fn simulate_transaction(
         &self,
         transaction_envelope: TransactionV1Envelope,
         ledger_info: &LedgerInfo,
+        options: SimulateOptions,
     ) -> Result<SimulateTransactionResponse> {
         let host_function_op = match &transaction_envelope.tx.operations[0].body {
             OperationBody::InvokeHostFunction(host) => host,
             _ => return Err(anyhow::anyhow!("Expected InvokeHostFunction operation")),
         };
 
-        let network_config = default_network_config()?;
+        let auth_mode = || match options.auth_mode {
+            Some(SimulationAuthMode::Enforce) => {
+                RecordingInvocationAuthMode::Enforcing(host_function_op.auth.to_vec())
+            },
+            Some(SimulationAuthMode::Record) => RecordingInvocationAuthMode::Recording(true),
+            Some(SimulationAuthMode::RecordAllowNonroot) => {
+                RecordingInvocationAuthMode::Recording(false)
+            },
+            None if !host_function_op.auth.is_empty() => {
+                RecordingInvocationAuthMode::Enforcing(host_function_op.auth.to_vec())
+            },
+            None => RecordingInvocationAuthMode::Recording(true),
+        };
+
+        // Same adjustments as stellar-rpc, so prepared transactions get the
+        // resources they would get there.
+        let mut adjustment_config = SimulationAdjustmentConfig::default_adjustment();
+        if let Some(leeway) = options.instruction_leeway {
+            adjustment_config.instructions.additive_factor = leeway;
+        }
+
+        let host_fn = &host_function_op.host_function;
+        let source_account = transaction_envelope.tx.source_account.account_id();
+        let snapshot = self.memory.snapshot_source();
         let simulation = simulate_invoke_host_function_op(
-            self.memory.clone(),
-            &network_config,
-            &SimulationAdjustmentConfig::no_adjustments(),
+            snapshot.clone(),
+            &default_network_config()?,
+            &adjustment_config,
             ledger_info,
-            host_function_op.host_function.clone(),
-            RecordingInvocationAuthMode::Recording(true),
-            &transaction_envelope.tx.source_account.account_id(),
+            host_fn.clone(),
+            auth_mode(),
+            &source_account,
             [1; 32],
             true,
-        )
-        .context("Failed to simulate invoke host function operation")?;
-
-        if simulation.invoke_result.is_err() {
-            let err = simulation.invoke_result.unwrap_err();
-            return Ok(SimulateTransactionResponse::Error(
-                SimulateTransactionErrorResponse {
-                    error: err.to_string(),
-                    id: "1".into(),
-                    parsed: true,
-                    events: simulation.diagnostic_events,
-                    latest_ledger: ledger_info.sequence_number,
-                },
-            ));
-        }
+        )?;
 
-        let changes = changes_from_simulation(simulation.modified_entries);
+        // The simulation keeps its budget to itself, so the run is repeated
+        // for the profile and replayed for the cost of each call.
+        let budget = unlimited_budget()?;
+        let recorded = e2e_invoke::invoke_host_function_in_recording_mode(
+            &budget,
+            false,
+            host_fn,
+            &source_account,
+            auth_mode(),
+            ledger_info.clone(),
+            snapshot.clone(),
+            [1; 32],
+            &mut vec![],
+        );
+        let changes = recorded
+            .as_ref()
+            .map_or(&[][..], |recorded| &recorded.ledger_changes);
+        let profile = self.profile(&budget, changes)?;
+        let frame_costs = replay_frame_costs(
+            host_fn,
+            &source_account,
+            &auth_mode(),
+            ledger_info,
+            snapshot,
+            [1; 32],
+        )?;
+        let trace = self.trace(&simulation.diagnostic_events, &frame_costs);
+        let events = simulation
+            .diagnostic_events
+            .iter()
+            .map(|event| event.to_xdr_base64(Limits::none()))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to convert events to XDR base64")?;
+
+        let retval = match simulation.invoke_result {
+            Ok(retval) => retval,
+            Err(err) => return Ok(simulation_error(ledger_info, events, err, trace, profile)),
+        };
         let tx_data = simulation
             .transaction_data
-            .ok_or_else(|| anyhow::anyhow!("Transaction data missing from simulation"))?;
+            .context("Missing transaction data of a successful simulation")?;
+        let changes = changes_from_simulation(simulation.modified_entries)?;
+
+        let footprint_diagnostics = match &transaction_envelope.tx.ext {
+            TransactionExt::V1(declared) => Some(footprint_diagnostics(
+                &declared.resources.footprint,
+                &tx_data.resources.footprint,
+            )?),
+            TransactionExt::V0 => None,
+        };
 
         let response = SimulateTransactionResponse::Success(SimulateTransactionSuccessResponse {
-            id: "1".into(),
             latest_ledger: ledger_info.sequence_number,
-            events: simulation.diagnostic_events,
+            events,
             min_resource_fee: tx_data.resource_fee.to_string(),
-            parsed: true,
-            result: Some(SimulateHostFunctionResult {
-                retval: simulation.invoke_result?.to_xdr_base64(Limits::none())?,
+            cost: SimulationCost {
+                cpu_insns: simulation.simulated_instructions.to_string(),
+                mem_bytes: simulation.simulated_memory.to_string(),
+            },
+            results: Some(vec![SimulateHostFunctionResult {
+                xdr: retval.to_xdr_base64(Limits::none())?,
                 auth: simulation
                     .auth
                     .into_iter()
                     .map(|auth| auth.to_xdr_base64(Limits::none()))
                     .collect::<Result<Vec<_>, _>>()
                     .context("Failed to convert auth to XDR base64")?,
-            }),
+            }]),
             state_changes: Some(changes),
+            footprint_diagnostics,
+            trace,
+            profile,
             transaction_data: tx_data
                 .to_xdr_base64(Limits::none())
                 .context("Failed to convert transaction data to XDR base64")?,
@@ -114,6 +247,7 @@ impl Executor {
         &self,
         transaction_envelope: &TransactionV1Envelope,
         ledger_info: &LedgerInfo,
+        auth_override: AuthOverride,
     ) -> Result<ExecutionResult> {
         ensure!(
             transaction_envelope.tx.operations.len() == 1,
@@ -130,25 +264,111 @@ impl Executor {
             _ => return Err(anyhow::anyhow!("Expected transaction extension V1")),
         };
 
-        let resources = &soroban_data.resources;
-
         let restored_entry_indices = match soroban_data.ext {
             SorobanTransactionDataExt::V1(ext) => ext.archived_soroban_entries.into_vec(),
             _ => vec![],
         };
 
-        let result = self.invoke_host_function(
-            &host_function_op.host_function,
-            resources,
-            &transaction_envelope.tx.source_account.clone().account_id(),
-            host_function_op.auth.clone().into_vec(),
-            &restored_entry_indices,
-            [0; 32],
-            true,
+        let source_account = transaction_envelope.tx.source_account.clone().account_id();
+
+        // The authorizations to mock are the ones a recording run requires,
+        // the transaction then runs with them recorded over its footprint.
+        let (execution, required_auth, recorded_footprint) = match auth_override {
+            AuthOverride::None => {
+                let execution = self.invoke_host_function(
+                    &host_function_op.host_function,
+                    &soroban_data.resources,
+                    &source_account,
+                    host_function_op.auth.to_vec(),
+                    &restored_entry_indices,
+                    [0; 32],
+                    true,
+                    ledger_info,
+                    self.memory.snapshot_source().as_ref(),
+                )?;
+
+                // The host only reports that a key is outside of the
+                // footprint, so the invocation is replayed with the footprint
+                // recorded to tell which.
+                let recorded_footprint = match &execution.result.encoded_invoke_result {
+                    Ok(_) => None,
+                    Err(_) => {
+                        let recorded = self.invoke_in_recording_mode(
+                            &host_function_op.host_function,
+                            &source_account,
+                            RecordingInvocationAuthMode::Enforcing(host_function_op.auth.to_vec()),
+                            ledger_info,
+                        )?;
+                        Some(recorded.result.resources.footprint)
+                    },
+                };
+
+                (execution, None, recorded_footprint)
+            },
+            _ => {
+                let recorded = self.invoke_in_recording_mode(
+                    &host_function_op.host_function,
+                    &source_account,
+                    RecordingInvocationAuthMode::Recording(false),
+                    ledger_info,
+                )?;
+                let execution = self.invoke_with_mocked_auth(
+                    &host_function_op.host_function,
+                    &soroban_data.resources,
+                    &source_account,
+                    &host_function_op.auth,
+                    &restored_entry_indices,
+                    &recorded.result.auth,
+                    ledger_info,
+                    &auth_override,
+                )?;
+
+                // Mocked authorizations consume random nonces, which are not
+                // part of the footprint to declare.
+                let recorded_footprint = match &execution.result.encoded_invoke_result {
+                    Ok(_) => None,
+                    Err(_) => {
+                        let mut footprint = recorded.result.resources.footprint;
+                        footprint.read_write = footprint
+                            .read_write
+                            .iter()
+                            .filter(|key| !is_nonce(key))
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .try_into()?;
+                        Some(footprint)
+                    },
+                };
+
+                (execution, Some(recorded.result.auth), recorded_footprint)
+            },
+        };
+        let footprint_diagnostics = recorded_footprint
+            .map(|recorded| footprint_diagnostics(&soroban_data.resources.footprint, &recorded))
+            .transpose()?
+            .filter(|diagnostics| !diagnostics.is_empty());
+        let trace = self.trace(&execution.diagnostic_events, &execution.frame_costs);
+        let result = execution.result;
+        let profile = self.profile(&execution.budget, &result.ledger_changes)?;
+
+        let events_size = result
+            .encoded_contract_events
+            .iter()
+            .chain(result.encoded_invoke_result.as_ref().ok())
+            .map(|encoded| encoded.len() as u32)
+            .sum();
+        let transaction_size = TransactionEnvelope::Tx(transaction_envelope.clone())
+            .to_xdr(Limits::none())?
+            .len() as u32;
+        let resource_fee = self.resource_fee(
+            profile.cpu_insns,
+            &result.ledger_changes,
+            events_size,
+            transaction_size,
             ledger_info,
         )?;
 
-        self.apply_ledger_changes(result.ledger_changes)?;
+        self.apply_ledger_changes(result.ledger_changes, ledger_info)?;
 
         let error = result
             .encoded_invoke_result
@@ -169,14 +389,152 @@ impl Executor {
         let result = ExecutionResult {
             error,
             fee_charges: transaction_envelope.tx.fee as i64,
+            resource_fee,
             result: out,
             events,
+            required_auth,
+            footprint_diagnostics,
+            trace,
+            profile,
+            diagnostic_events: execution.diagnostic_events,
         };
 
         Ok(result)
     }
 
-    pub fn apply_ledger_changes(&self, changes: Vec<LedgerEntryChange>) -> Result<()> {
+    /// Resource fee of a recording run, sent by an account with a single
+    /// signature.
+    pub fn recorded_fee(
+        &self,
+        host_fn: &HostFunction,
+        recorded: &RecordingExecutionResult,
+        ledger_info: &LedgerInfo,
+    ) -> Result<i64> {
+        let result = &recorded.result;
+        let restored = &result.restored_rw_entry_indices;
+        let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: Transaction {
+                source_account: MuxedAccount::Ed25519(Uint256([0; 32])),
+                fee: 0,
+                seq_num: SequenceNumber(0),
+                cond: Preconditions::None,
+                memo: Memo::None,
+                operations: vec![Operation {
+                    source_account: None,
+                    body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                        host_function: host_fn.clone(),
+                        auth: result.auth.clone().try_into()?,
+                    }),
+                }]
+                .try_into()?,
+                ext: TransactionExt::V1(SorobanTransactionData {
+                    resources: result.resources.clone(),
+                    resource_fee: 0,
+                    ext: match restored.is_empty() {
+                        true => SorobanTransactionDataExt::V0,
+                        false => SorobanTransactionDataExt::V1(SorobanResourcesExtV0 {
+                            archived_soroban_entries: restored.clone().try_into()?,
+                        }),
+                    },
+                }),
+            },
+            signatures: vec![DecoratedSignature {
+                hint: SignatureHint([0; 4]),
+                signature: Signature(vec![0; 64].try_into()?),
+            }]
+            .try_into()?,
+        });
+        let transaction_size = envelope.to_xdr(Limits::none())?.len() as u32;
+
+        self.resource_fee(
+            recorded.cpu_insns,
+            &result.ledger_changes,
+            result.contract_events_and_return_value_size,
+            transaction_size,
+            ledger_info,
+        )
+    }
+
+    /// Resource fee of what an invocation used against the current state, the
+    /// changes must not be applied yet.
+    fn resource_fee(
+        &self,
+        instructions: u64,
+        changes: &[LedgerEntryChange],
+        contract_events_size_bytes: u32,
+        transaction_size_bytes: u32,
+        ledger_info: &LedgerInfo,
+    ) -> Result<i64> {
+        resource_fee(
+            &default_network_config()?,
+            self.memory.as_ref(),
+            instructions,
+            changes,
+            contract_events_size_bytes,
+            transaction_size_bytes,
+            ledger_info,
+        )
+    }
+
+    fn trace(&self, events: &[DiagnosticEvent], costs: &[FrameCost]) -> InvocationTrace {
+        InvocationTrace::from_events(events, costs, &|contract| {
+            self.specs.contract_spec(&self.memory, contract)
+        })
+    }
+
+    /// Budget spent per cost type, along with the entries read and written
+    /// by `changes`. The changes must not be applied yet, read bytes are the
+    /// sizes of the entries before the invocation.
+    pub fn profile(&self, budget: &Budget, changes: &[LedgerEntryChange]) -> Result<CostProfile> {
+        let mut cost_types = vec![];
+        for ty in ContractCostType::variants() {
+            let tracker = budget.get_tracker(ty)?;
+            if tracker.iterations == 0 {
+                continue;
+            }
+            cost_types.push(CostTypeUsage {
+                cost_type: ty.name().to_string(),
+                iterations: tracker.iterations,
+                input: tracker.inputs,
+                cpu_insns: tracker.cpu,
+                mem_bytes: tracker.mem,
+            });
+        }
+        cost_types.sort_by(|a, b| b.cpu_insns.cmp(&a.cpu_insns));
+
+        let mut profile = CostProfile {
+            cpu_insns: budget.get_cpu_insns_consumed()?,
+            mem_bytes: budget.get_mem_bytes_consumed()?,
+            cost_types,
+            ..Default::default()
+        };
+        for change in changes {
+            let key = LedgerKey::from_xdr(&change.encoded_key, Limits::none())
+                .context("Failed to decode ledger key from XDR")?;
+            if let Some((entry, _)) = self.memory.get(&Rc::new(key))? {
+                profile.read_bytes += entry.to_xdr(Limits::none())?.len() as u32;
+            }
+            profile.read_entries += 1;
+
+            if !change.read_only {
+                profile.write_entries += 1;
+                profile.write_bytes += change
+                    .encoded_new_value
+                    .as_ref()
+                    .map_or(0, |value| value.len() as u32);
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Writes the changes into memory. Created code is compiled into the
+    /// module store right away.
+    pub fn apply_ledger_changes(
+        &self,
+        changes: Vec<LedgerEntryChange>,
+        ledger_info: &LedgerInfo,
+    ) -> Result<()> {
         for change in changes {
             let key = LedgerKey::from_xdr(change.encoded_key, Limits::none())
                 .context("Failed to decode ledger key from XDR")?;
@@ -187,13 +545,17 @@ impl Executor {
                 Some(encoded_entry) => {
                     let entry = LedgerEntry::from_xdr(encoded_entry, Limits::none())
                         .context("Failed to decode ledger entry from XDR")?;
+                    if let LedgerEntryData::ContractCode(code) = &entry.data {
+                        self.modules.add(ledger_info.protocol_version, code)?;
+                        self.specs.add(code);
+                    }
                     self.memory.insert_with_ttl(entry, ttl);
                 },
                 None if !change.read_only => {
-                    self.memory.remove(&Rc::new(key));
+                    self.memory.remove(&key);
                 },
                 _ => {
-                    self.memory.update_ttl(&Rc::new(key), ttl);
+                    self.memory.update_ttl(&key, ttl);
                 },
             }
         }
@@ -201,6 +563,163 @@ impl Executor {
         Ok(())
     }
 
+    /// Invokes the host function against the current state with the footprint
+    /// recorded on the fly. Changes are not applied, the caller decides what to
+    /// do with `ledger_changes`.
+    pub fn invoke_in_recording_mode(
+        &self,
+        host_fn: &HostFunction,
+        source_account: &AccountId,
+        auth_mode: RecordingInvocationAuthMode,
+        ledger_info: &LedgerInfo,
+    ) -> Result<RecordingExecutionResult> {
+        let budget = unlimited_budget()?;
+        let mut diagnostic_events = Vec::new();
+        let result = e2e_invoke::invoke_host_function_in_recording_mode(
+            &budget,
+            true,
+            host_fn,
+            source_account,
+            auth_mode,
+            ledger_info.clone(),
+            self.memory.snapshot_source(),
+            [0; 32],
+            &mut diagnostic_events,
+        )
+        .context("Failed to invoke host function in recording mode")?;
+
+        Ok(RecordingExecutionResult {
+            result,
+            diagnostic_events,
+            cpu_insns: budget.get_cpu_insns_consumed()?,
+            mem_bytes: budget.get_mem_bytes_consumed()?,
+            budget,
+        })
+    }
+
+    /// Invokes the host function over the entries of the declared footprint,
+    /// with the authorizations recorded rather than checked. The entries of
+    /// the addresses `auth_override` doesn't mock are then verified, so only
+    /// the mocked ones may go without a signature.
+    #[allow(clippy::too_many_arguments)]
+    fn invoke_with_mocked_auth(
+        &self,
+        host_fn: &HostFunction,
+        resources: &SorobanResources,
+        source_account: &AccountId,
+        auth_entries: &[SorobanAuthorizationEntry],
+        restored_entry_indices: &[u32],
+        required_auth: &[SorobanAuthorizationEntry],
+        ledger_info: &LedgerInfo,
+        auth_override: &AuthOverride,
+    ) -> Result<HostFunctionExecution> {
+        let declared = DeclaredFootprint::new(self.memory.snapshot_source(), &resources.footprint);
+        let addresses = required_auth
+            .iter()
+            .filter_map(|entry| match &entry.credentials {
+                SorobanCredentials::Address(credentials) => Some(&credentials.address),
+                SorobanCredentials::SourceAccount => None,
+            });
+        let snapshot = Rc::new(
+            DeclaredFootprint::new(self.memory.snapshot_source(), &resources.footprint)
+                .with_auth_emulation(addresses)?,
+        );
+
+        let budget = unlimited_budget()?;
+        let mut diagnostic_events = Vec::new();
+        let recorded = e2e_invoke::invoke_host_function_in_recording_mode(
+            &budget,
+            true,
+            host_fn,
+            source_account,
+            RecordingInvocationAuthMode::Recording(false),
+            ledger_info.clone(),
+            snapshot.clone(),
+            [0; 32],
+            &mut diagnostic_events,
+        );
+        let frame_costs = replay_frame_costs(
+            host_fn,
+            source_account,
+            &RecordingInvocationAuthMode::Recording(false),
+            ledger_info,
+            snapshot,
+            [0; 32],
+        )
+        .unwrap_or_default();
+
+        let verified = recorded.and_then(|recorded| {
+            let value = recorded.invoke_result.clone()?;
+            check_access(
+                &resources.footprint,
+                restored_entry_indices,
+                &recorded.resources.footprint,
+                &recorded.restored_rw_entry_indices,
+            )?;
+            Ok((value, recorded))
+        });
+        let (value, recorded) = match verified {
+            Ok(verified) => verified,
+            Err(err) => {
+                return Ok(HostFunctionExecution {
+                    result: InvokeHostFunctionResult {
+                        encoded_invoke_result: Err(err),
+                        ledger_changes: vec![],
+                        encoded_contract_events: vec![],
+                    },
+                    diagnostic_events,
+                    frame_costs,
+                    budget,
+                })
+            },
+        };
+        let nonces = verify_unmocked(
+            Rc::new(declared),
+            ledger_info,
+            source_account,
+            auth_entries,
+            &recorded.auth,
+            auth_override,
+        )?;
+
+        let declared_keys: BTreeSet<_> = resources
+            .footprint
+            .read_only
+            .iter()
+            .chain(resources.footprint.read_write.iter())
+            .collect();
+        let mut ledger_changes = vec![];
+        for change in recorded.ledger_changes {
+            let key = LedgerKey::from_xdr(&change.encoded_key, Limits::none())?;
+            if declared_keys.contains(&key) && !is_nonce(&key) {
+                ledger_changes.push(change);
+            }
+        }
+        let result = match nonces {
+            Ok(nonces) => InvokeHostFunctionResult {
+                encoded_invoke_result: Ok(value.to_xdr(Limits::none())?),
+                ledger_changes: ledger_changes.into_iter().chain(nonces).collect(),
+                encoded_contract_events: recorded
+                    .contract_events
+                    .iter()
+                    .map(|event| event.to_xdr(Limits::none()))
+                    .collect::<Result<_, _>>()?,
+            },
+            Err(err) => InvokeHostFunctionResult {
+                encoded_invoke_result: Err(err),
+                ledger_changes: vec![],
+                encoded_contract_events: vec![],
+            },
+        };
+
+        Ok(HostFunctionExecution {
+            result,
+            diagnostic_events,
+            frame_costs,
+            budget,
+        })
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn invoke_host_function(
         &self,
@@ -212,7 +731,8 @@ impl Executor {
         prng_seed: [u8; 32],
         enable_diagnostics: bool,
         ledger_info: &LedgerInfo,
-    ) -> Result<InvokeHostFunctionResult> {
+        snapshot: &dyn SnapshotSource,
+    ) -> Result<HostFunctionExecution> {
         let limits = Limits::none();
 
         let encoded_host_fn = host_fn
@@ -243,8 +763,7 @@ impl Executor {
             .chain(resources.footprint.read_write.iter());
 
         for key in all_keys {
-            if let Some((entry_rc, ttl)) = self
-                .memory
+            if let Some((entry_rc, ttl)) = snapshot
                 .get(&Rc::new(key.clone()))
                 .context("Failed to get entry from memory")?
             {
@@ -262,9 +781,11 @@ impl Executor {
             .collect();
         let encoded_ledger_entries = encoded_ledger_entries?;
 
+        // The host expects a TTL buffer for every ledger entry, empty for the
+        // entries that don't have one.
         let encoded_ttl_entries: Result<Vec<Vec<u8>>, _> = entries_with_ttl
             .iter()
-            .filter_map(|(entry, ttl)| {
+            .map(|(entry, ttl)| {
                 let key = match &entry.data {
                     LedgerEntryData::ContractData(cd) => {
                         Some(LedgerKey::ContractData(LedgerKeyContractData {
@@ -281,9 +802,10 @@ impl Executor {
                     _ => None,
                 };
 
-                key.and_then(|k| {
-                    ttl.map(|ttl_value| ttl_entry(&k, ttl_value).to_xdr(limits.clone()))
-                })
+                match key.zip(*ttl) {
+                    Some((k, ttl_value)) => ttl_entry(&k, ttl_value).to_xdr(limits.clone()),
+                    None => Ok(vec![]),
+                }
             })
             .collect();
 
@@ -298,57 +820,16 @@ impl Executor {
             }
         }
 
-        let ledger_entries_for_cache: Vec<(LedgerEntry, Option<u32>)> = entries_with_ttl
-            .iter()
-            .map(|(entry_rc, ttl)| ((**entry_rc).clone(), *ttl))
-            .collect();
-
-        let module_cache = build_module_cache_for_entries(
-            ledger_info,
-            ledger_entries_for_cache,
+        let module_cache = self.modules.cache_for_entries(
+            ledger_info.protocol_version,
+            &entries_with_ttl,
             &restored_contracts,
         )?;
 
-        let cpu_cost_params = ContractCostParams(
-            vec![
-                ContractCostParamEntry {
-                    ext: ExtensionPoint::V0,
-                    const_term: 35,
-                    linear_term: 36,
-                },
-                ContractCostParamEntry {
-                    ext: ExtensionPoint::V0,
-                    const_term: 37,
-                    linear_term: 38,
-                },
-            ]
-            .try_into()?,
-        );
-        let mem_cost_params = ContractCostParams(
-            vec![
-                ContractCostParamEntry {
-                    ext: ExtensionPoint::V0,
-                    const_term: 39,
-                    linear_term: 40,
-                },
-                ContractCostParamEntry {
-                    ext: ExtensionPoint::V0,
-                    const_term: 41,
-                    linear_term: 42,
-                },
-                ContractCostParamEntry {
-                    ext: ExtensionPoint::V0,
-                    const_term: 43,
-                    linear_term: 44,
-                },
-            ]
-            .try_into()?,
-        );
-
-        let budget =
-            Budget::try_from_configs(u64::MAX, u64::MAX, cpu_cost_params, mem_cost_params)?;
+        let budget = unlimited_budget()?;
 
         let mut diagnostic_events = Vec::new();
+        let costs = Rc::new(RefCell::new(FrameCostRecorder::default()));
 
         let result = e2e_invoke::invoke_host_function(
             &budget,
@@ -363,11 +844,40 @@ impl Executor {
             encoded_ttl_entries.into_iter(),
             prng_seed.to_vec(),
             &mut diagnostic_events,
-            None,
+            Some(FrameCostRecorder::hook(costs.clone())),
             Some(module_cache),
         )
         .context("Failed to invoke host function")?;
 
-        Ok(result)
+        Ok(HostFunctionExecution {
+            result,
+            diagnostic_events,
+            frame_costs: costs.take().into_costs(),
+            budget,
+        })
     }
 }
+
+/// Budget with the cost models of the host and no limits.
+fn unlimited_budget() -> Result<Budget> {
+    let budget = Budget::default();
+    budget.reset_unlimited()?;
+
+    Ok(budget)
+}
+
+fn simulation_error(
+    ledger_info: &LedgerInfo,
+    events: Vec<String>,
+    error: HostError,
+    trace: InvocationTrace,
+    profile: CostProfile,
+) -> SimulateTransactionResponse {
+    SimulateTransactionResponse::Error(SimulateTransactionErrorResponse {
+        latest_ledger: ledger_info.sequence_number,
+        events,
+        error: error.to_string(),
+        trace,
+        profile,
+    })
+}
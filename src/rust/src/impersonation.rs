@@ -0,0 +1,271 @@
+//! Transactions sent with the `require_auth` of some addresses mocked. They
+//! run with recorded authorizations over a snapshot that only gives the
+//! entries of their declared footprint, then the entries of the addresses that
+//! are not mocked are checked the way the enforcing mode does.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use soroban_env_common::{AddressObject, Symbol, TryFromVal, Val, VecObject};
+use soroban_env_host::{
+    budget::Budget,
+    e2e_invoke::{LedgerEntryChange, LedgerEntryLiveUntilChange},
+    storage::{EntryWithLiveUntil, SnapshotSource, Storage},
+    xdr::{
+        AccountId, ContractDataDurability, ContractExecutable, ContractId, HostFunction,
+        LedgerEntryData, LedgerEntryType, LedgerFootprint, LedgerKey, LedgerKeyAccount,
+        LedgerKeyContractCode, LedgerKeyContractData, Limits, ScAddress, ScErrorCode, ScErrorType,
+        ScVal, ScVec, SorobanAuthorizationEntry, SorobanAuthorizedFunction, SorobanCredentials,
+        WriteXdr,
+    },
+    Env, Host, HostError, LedgerInfo,
+};
+
+use crate::executor::AuthOverride;
+
+/// Snapshot giving only the entries of a declared footprint, like the storage
+/// of the enforcing mode. Nonces are let through since recorded
+/// authorizations consume random ones.
+pub struct DeclaredFootprint {
+    inner: Rc<dyn SnapshotSource>,
+    keys: BTreeSet<LedgerKey>,
+}
+
+impl DeclaredFootprint {
+    pub fn new(inner: Rc<dyn SnapshotSource>, footprint: &LedgerFootprint) -> Self {
+        let keys = footprint
+            .read_only
+            .iter()
+            .chain(footprint.read_write.iter())
+            .cloned()
+            .collect();
+
+        Self { inner, keys }
+    }
+
+    /// Also gives the entries the recording mode reads to emulate the
+    /// authentication of `addresses`.
+    pub fn with_auth_emulation<'a>(
+        mut self,
+        addresses: impl IntoIterator<Item = &'a ScAddress>,
+    ) -> Result<Self> {
+        for address in addresses {
+            match address {
+                ScAddress::Contract(contract) => {
+                    let instance = instance_key(contract);
+                    if let Some((entry, _)) = self.inner.get(&Rc::new(instance.clone()))? {
+                        if let Some(code) = wasm_key(&entry.data) {
+                            self.keys.insert(code);
+                        }
+                    }
+                    self.keys.insert(instance);
+                },
+                ScAddress::Account(account_id) => {
+                    self.keys.insert(LedgerKey::Account(LedgerKeyAccount {
+                        account_id: account_id.clone(),
+                    }));
+                },
+                _ => {},
+            }
+        }
+
+        Ok(self)
+    }
+}
+
+impl SnapshotSource for DeclaredFootprint {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        if !self.keys.contains(key) && !is_nonce(key) {
+            return Err((ScErrorType::Storage, ScErrorCode::ExceededLimit).into());
+        }
+
+        self.inner.get(key)
+    }
+}
+
+/// Checks that the recorded authorizations of the addresses that are not
+/// mocked have a matching entry in `provided`, and verifies the signatures of
+/// those entries. Returns the changes of the nonces they consume, or the
+/// error the transaction fails with.
+pub fn verify_unmocked(
+    snapshot: Rc<DeclaredFootprint>,
+    ledger_info: &LedgerInfo,
+    source_account: &AccountId,
+    provided: &[SorobanAuthorizationEntry],
+    recorded: &[SorobanAuthorizationEntry],
+    auth_override: &AuthOverride,
+) -> Result<Result<Vec<LedgerEntryChange>, HostError>> {
+    let source = ScAddress::Account(source_account.clone());
+    let address = |entry: &SorobanAuthorizationEntry| match &entry.credentials {
+        SorobanCredentials::SourceAccount => source.clone(),
+        SorobanCredentials::Address(credentials) => credentials.address.clone(),
+    };
+
+    let mut entries = vec![];
+    for required in recorded {
+        if auth_override.mocks(&address(required)) {
+            continue;
+        }
+        let entry = provided.iter().find(|entry| {
+            entry.root_invocation == required.root_invocation
+                && match (&entry.credentials, &required.credentials) {
+                    (SorobanCredentials::SourceAccount, SorobanCredentials::SourceAccount) => true,
+                    (SorobanCredentials::Address(entry), SorobanCredentials::Address(required)) => {
+                        entry.address == required.address
+                    },
+                    _ => false,
+                }
+        });
+        match entry {
+            Some(entry) => entries.push(entry.clone()),
+            None => return Ok(Err((ScErrorType::Auth, ScErrorCode::InvalidAction).into())),
+        }
+    }
+
+    // Signatures are checked against the source account, their nonces are
+    // consumed in the same storage so an entry can't be used twice.
+    let budget = Budget::default();
+    budget.reset_unlimited()?;
+    let host = Host::with_storage_and_budget(
+        Storage::with_recording_footprint(snapshot.clone()),
+        budget.clone(),
+    );
+    host.set_source_account(source_account.clone())?;
+    host.set_ledger_info(ledger_info.clone())?;
+    for entry in entries {
+        let SorobanCredentials::Address(credentials) = &entry.credentials else {
+            continue;
+        };
+        let address = credentials.address.clone();
+        host.set_authorization_entries(vec![entry.clone()])?;
+        let verified = match &entry.root_invocation.function {
+            SorobanAuthorizedFunction::ContractFn(call) => {
+                let ScAddress::Contract(contract) = &call.contract_address else {
+                    return Ok(Err((ScErrorType::Auth, ScErrorCode::InvalidInput).into()));
+                };
+                let function =
+                    Symbol::try_from_val(&host, &call.function_name).map_err(HostError::from)?;
+                host.with_test_contract_frame(contract.clone(), function, || {
+                    let address = Val::try_from_val(&host, &ScVal::Address(address))?;
+                    let args =
+                        Val::try_from_val(&host, &ScVal::Vec(Some(ScVec(call.args.clone()))))?;
+                    host.require_auth_for_args(
+                        AddressObject::try_from_val(&host, &address)?,
+                        VecObject::try_from_val(&host, &args)?,
+                    )
+                    .map(Val::from)
+                })
+            },
+            SorobanAuthorizedFunction::CreateContractHostFn(args) => host
+                .invoke_function(HostFunction::CreateContract(args.clone()))
+                .map(|_| Val::VOID.into()),
+            SorobanAuthorizedFunction::CreateContractV2HostFn(args) => host
+                .invoke_function(HostFunction::CreateContractV2(args.clone()))
+                .map(|_| Val::VOID.into()),
+        };
+        if let Err(err) = verified {
+            return Ok(Err(err));
+        }
+    }
+
+    let (storage, _) = host.try_finish()?;
+    let mut changes = vec![];
+    for (key, entry) in storage.map.iter(&budget)? {
+        let Some((entry, Some(live_until))) = entry else {
+            continue;
+        };
+        if !is_nonce(key) || snapshot.inner.get(key)?.is_some() {
+            continue;
+        }
+        if !snapshot.keys.contains(key) {
+            return Ok(Err(
+                (ScErrorType::Storage, ScErrorCode::ExceededLimit).into()
+            ));
+        }
+
+        let encoded_key = key.to_xdr(Limits::none())?;
+        let encoded_entry = entry.to_xdr(Limits::none())?;
+        changes.push(LedgerEntryChange {
+            read_only: false,
+            old_entry_size_bytes_for_rent: 0,
+            new_entry_size_bytes_for_rent: encoded_entry.len() as u32,
+            encoded_new_value: Some(encoded_entry),
+            ttl_change: Some(LedgerEntryLiveUntilChange {
+                key_hash: Sha256::digest(&encoded_key).to_vec(),
+                durability: ContractDataDurability::Temporary,
+                entry_type: LedgerEntryType::ContractData,
+                old_live_until_ledger: 0,
+                new_live_until_ledger: *live_until,
+            }),
+            encoded_key,
+        });
+    }
+
+    Ok(Ok(changes))
+}
+
+/// Checks that a run over the declared footprint only wrote to read-write
+/// keys and only restored the keys marked as archived. Returns the error the
+/// transaction fails with otherwise.
+pub fn check_access(
+    declared: &LedgerFootprint,
+    declared_restored: &[u32],
+    recorded: &LedgerFootprint,
+    recorded_restored: &[u32],
+) -> Result<(), HostError> {
+    let read_write: BTreeMap<_, _> = declared.read_write.iter().zip(0_u32..).collect();
+    for key in recorded.read_write.iter().filter(|key| !is_nonce(key)) {
+        if !read_write.contains_key(key) {
+            return Err((ScErrorType::Storage, ScErrorCode::ExceededLimit).into());
+        }
+    }
+    for index in recorded_restored {
+        let restored = recorded
+            .read_write
+            .get(*index as usize)
+            .and_then(|key| read_write.get(key))
+            .is_some_and(|index| declared_restored.contains(index));
+        if !restored {
+            return Err((ScErrorType::Storage, ScErrorCode::InvalidInput).into());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_nonce(key: &LedgerKey) -> bool {
+    matches!(
+        key,
+        LedgerKey::ContractData(LedgerKeyContractData {
+            key: ScVal::LedgerKeyNonce(_),
+            ..
+        })
+    )
+}
+
+fn instance_key(contract: &ContractId) -> LedgerKey {
+    LedgerKey::ContractData(LedgerKeyContractData {
+        contract: ScAddress::Contract(contract.clone()),
+        key: ScVal::LedgerKeyContractInstance,
+        durability: ContractDataDurability::Persistent,
+    })
+}
+
+fn wasm_key(instance: &LedgerEntryData) -> Option<LedgerKey> {
+    let LedgerEntryData::ContractData(data) = instance else {
+        return None;
+    };
+    let ScVal::ContractInstance(instance) = &data.val else {
+        return None;
+    };
+    match &instance.executable {
+        ContractExecutable::Wasm(hash) => Some(LedgerKey::ContractCode(LedgerKeyContractCode {
+            hash: hash.clone(),
+        })),
+        ContractExecutable::StellarAsset => None,
+    }
+}
@@ -0,0 +1,142 @@
+use im::Vector;
+use sha2::{Digest, Sha256};
+use soroban_env_host::{
+    xdr::{
+        Hash, LedgerCloseMeta, LedgerCloseMetaV0, LedgerHeader, LedgerHeaderHistoryEntry,
+        LedgerHeaderHistoryEntryExt, Limits, StellarValue, TimePoint, TransactionSet, WriteXdr,
+    },
+    LedgerInfo,
+};
+
+/// Header of a ledger closed by the sandbox.
+#[derive(Clone)]
+pub struct LedgerHeaderInfo {
+    pub sequence: u32,
+    pub hash: Hash,
+    pub close_time: u64,
+    pub protocol_version: u32,
+    header: LedgerHeader,
+}
+
+impl LedgerHeaderInfo {
+    pub fn header_entry(&self) -> LedgerHeaderHistoryEntry {
+        LedgerHeaderHistoryEntry {
+            hash: self.hash.clone(),
+            header: self.header.clone(),
+            ext: LedgerHeaderHistoryEntryExt::V0,
+        }
+    }
+
+    /// Close meta of the ledger, transactions are not included.
+    pub fn close_meta(&self) -> LedgerCloseMeta {
+        LedgerCloseMeta::V0(LedgerCloseMetaV0 {
+            ledger_header: self.header_entry(),
+            tx_set: TransactionSet {
+                previous_ledger_hash: self.header.previous_ledger_hash.clone(),
+                txs: Default::default(),
+            },
+            tx_processing: Default::default(),
+            upgrades_processing: Default::default(),
+            scp_info: Default::default(),
+        })
+    }
+}
+
+/// Ledgers kept by default, 7 days of ledgers just like stellar-rpc.
+pub const DEFAULT_RETENTION_WINDOW: u32 = 120_960;
+
+/// Headers of the ledgers the sandbox went through within the retention
+/// window, oldest first. The headers are kept in a persistent vector, so
+/// clones share them.
+#[derive(Clone)]
+pub struct LedgerHistory {
+    ledgers: Vector<LedgerHeaderInfo>,
+    retention_window: u32,
+}
+
+impl LedgerHistory {
+    pub fn new(ledger_info: &LedgerInfo) -> Self {
+        let mut history = Self {
+            ledgers: Vector::new(),
+            retention_window: DEFAULT_RETENTION_WINDOW,
+        };
+        history.close(ledger_info);
+
+        history
+    }
+
+    pub fn retention_window(&self) -> u32 {
+        self.retention_window
+    }
+
+    /// Sets how many ledgers back from the latest one are kept, the latest
+    /// ledger is always kept.
+    pub fn set_retention_window(&mut self, retention_window: u32) {
+        self.retention_window = retention_window.max(1);
+        self.prune();
+    }
+
+    fn prune(&mut self) {
+        let latest = self.latest().sequence;
+        let window = self.retention_window;
+
+        self.ledgers
+            .retain(|ledger| latest - ledger.sequence < window);
+    }
+
+    /// Records the header of the current ledger. Headers of the same or later
+    /// sequence numbers are replaced, so moving the sequence back rewrites
+    /// history.
+    pub fn close(&mut self, ledger_info: &LedgerInfo) {
+        self.ledgers
+            .retain(|ledger| ledger.sequence < ledger_info.sequence_number);
+
+        let previous_ledger_hash = self
+            .ledgers
+            .last()
+            .map(|ledger| ledger.hash.clone())
+            .unwrap_or(Hash([0; 32]));
+
+        let header = LedgerHeader {
+            ledger_version: ledger_info.protocol_version,
+            previous_ledger_hash,
+            scp_value: StellarValue {
+                close_time: TimePoint(ledger_info.timestamp),
+                ..Default::default()
+            },
+            ledger_seq: ledger_info.sequence_number,
+            base_reserve: ledger_info.base_reserve,
+            ..Default::default()
+        };
+        let hash = Sha256::digest(
+            header
+                .to_xdr(Limits::none())
+                .expect("ledger header is serializable"),
+        );
+
+        self.ledgers.push_back(LedgerHeaderInfo {
+            sequence: ledger_info.sequence_number,
+            hash: Hash(hash.into()),
+            close_time: ledger_info.timestamp,
+            protocol_version: ledger_info.protocol_version,
+            header,
+        });
+        self.prune();
+    }
+
+    pub fn latest(&self) -> &LedgerHeaderInfo {
+        self.ledgers.back().expect("history always has a ledger")
+    }
+
+    pub fn oldest(&self) -> &LedgerHeaderInfo {
+        self.ledgers.front().expect("history always has a ledger")
+    }
+
+    /// Ledgers starting at the given sequence number, at most `limit` of them.
+    pub fn range(&self, start: u32, limit: usize) -> impl Iterator<Item = &LedgerHeaderInfo> {
+        self.ledgers
+            .iter()
+            .skip_while(move |ledger| ledger.sequence < start)
+            .take(limit)
+    }
+}
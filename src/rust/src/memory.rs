@@ -1,7 +1,11 @@
-use std::{cell::RefCell, collections::BTreeMap, fmt, rc::Rc};
+use std::{
+    fmt,
+    rc::Rc,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
 use anyhow::{anyhow, Result};
-use napi::Error;
+use im::OrdMap;
 use soroban_env_common::xdr::LedgerEntryData;
 use soroban_env_host::{
     storage::{EntryWithLiveUntil, SnapshotSource},
@@ -9,16 +13,29 @@ use soroban_env_host::{
     HostError,
 };
 
-type StorageMap = BTreeMap<Rc<LedgerKey>, EntryWithLiveUntil>;
+/// Persistent map of the ledger entries, a clone shares its nodes with the
+/// original and a write copies only the path to the changed entry.
+type StorageMap = OrdMap<Arc<LedgerKey>, (Arc<LedgerEntry>, Option<u32>)>;
 
-#[derive(Default, Clone)]
+/// Ledger entries with their TTLs. The map is behind a lock so the sandbox
+/// can be shared with worker threads, a clone shares the entries until
+/// either side changes them.
+#[derive(Default)]
 pub struct Memory {
-    memory: RefCell<StorageMap>,
+    memory: RwLock<StorageMap>,
+}
+
+impl Clone for Memory {
+    fn clone(&self) -> Self {
+        Self {
+            memory: RwLock::new(self.read().clone()),
+        }
+    }
 }
 
 impl fmt::Debug for Memory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let memory = self.memory.borrow();
+        let memory = self.read();
 
         let mut map = f.debug_map();
         for (key, (entry, ttl)) in memory.iter() {
@@ -37,26 +54,29 @@ impl Memory {
     }
 
     pub fn insert_with_ttl(&self, entry: LedgerEntry, ttl: Option<u32>) {
-        self.memory
-            .borrow_mut()
-            .insert(Rc::new(entry.to_key()), (Rc::new(entry), ttl));
+        self.write()
+            .insert(Arc::new(entry.to_key()), (Arc::new(entry), ttl));
     }
 
-    pub fn update_ttl(&self, key: &Rc<LedgerKey>, new_ttl: Option<u32>) {
-        self.memory
-            .borrow_mut()
-            .entry(key.clone())
-            .and_modify(|(_, ttl)| *ttl = new_ttl);
+    pub fn update_ttl(&self, key: &LedgerKey, new_ttl: Option<u32>) {
+        if let Some((_, ttl)) = self.write().get_mut(key) {
+            *ttl = new_ttl;
+        }
+    }
+
+    pub fn remove(&self, key: &LedgerKey) {
+        self.write().remove(key);
     }
 
-    pub fn remove(&self, key: &Rc<LedgerKey>) {
-        self.memory.borrow_mut().remove(key);
+    /// The memory as a host snapshot source, which has to be an `Rc`.
+    pub fn snapshot_source(self: &Arc<Self>) -> Rc<dyn SnapshotSource> {
+        Rc::new(SharedMemory(self.clone()))
     }
 
     pub fn get_account(&self, key: Rc<LedgerKey>) -> Result<Option<AccountEntry>> {
         let entry = self
             .get(&key)
-            .map_err(|e| Error::from_reason(format!("memory access error: {:?}", e)))?;
+            .map_err(|e| anyhow!("memory access error: {:?}", e))?;
 
         let entry = match entry {
             Some((entry, _)) => entry,
@@ -68,12 +88,30 @@ impl Memory {
             _ => Err(anyhow!("account not found")),
         }
     }
+
+    // Every write is a single map operation, so a panic while holding the
+    // lock can't leave the map half updated.
+    fn read(&self) -> RwLockReadGuard<'_, StorageMap> {
+        self.memory.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, StorageMap> {
+        self.memory.write().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 impl SnapshotSource for Memory {
     fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
-        let entry = self.memory.borrow().get(key).cloned();
+        let entry = self.read().get(key.as_ref()).cloned();
+
+        Ok(entry.map(|(entry, ttl)| (Rc::new((*entry).clone()), ttl)))
+    }
+}
 
-        Ok(entry)
+struct SharedMemory(Arc<Memory>);
+
+impl SnapshotSource for SharedMemory {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        self.0.get(key)
     }
 }
@@ -1,10 +1,69 @@
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
 use soroban_env_common::xdr::{TransactionEvent, TransactionResult};
-use soroban_env_host::xdr::{
-    ContractEvent, DiagnosticEvent, LedgerEntry, LedgerEntryChangeType, LedgerKey,
+use soroban_env_host::{
+    e2e_invoke::RecordingInvocationAuthMode,
+    xdr::{ContractDataDurability, ContractEvent, DiagnosticEvent, LedgerEntry, LedgerKey},
 };
 
+use crate::trace::InvocationTrace;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkInfo {
+    pub passphrase: String,
+    pub protocol_version: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLatestLedgerResponse {
+    pub id: String,
+    pub sequence: u32,
+    pub protocol_version: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetHealthResponse {
+    pub status: String,
+    pub latest_ledger: u32,
+    pub oldest_ledger: u32,
+    pub ledger_retention_window: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetVersionInfoResponse {
+    pub version: String,
+    pub commit_hash: String,
+    pub build_timestamp: String,
+    pub captive_core_version: String,
+    pub protocol_version: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerResponse {
+    pub hash: String,
+    pub sequence: u32,
+    pub ledger_close_time: String,
+    pub header_xdr: String,
+    pub metadata_xdr: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLedgersResponse {
+    pub ledgers: Vec<LedgerResponse>,
+    pub latest_ledger: u32,
+    pub latest_ledger_close_time: u64,
+    pub oldest_ledger: u32,
+    pub oldest_ledger_close_time: u64,
+    pub cursor: String,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum SimulateTransactionResponse {
@@ -12,48 +71,203 @@ pub enum SimulateTransactionResponse {
     Error(SimulateTransactionErrorResponse),
 }
 
-#[serde_as]
+/// Successful `simulateTransaction` response, in the stellar-rpc format.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SimulateTransactionSuccessResponse {
-    pub id: String,
     pub latest_ledger: u32,
-    pub events: Vec<DiagnosticEvent>,
-    #[serde(rename = "_parsed")]
-    pub parsed: bool,
+    /// Base64 `DiagnosticEvent` XDR.
+    pub events: Vec<String>,
     pub transaction_data: String,
     pub min_resource_fee: String,
+    pub cost: SimulationCost,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub result: Option<SimulateHostFunctionResult>,
+    pub results: Option<Vec<SimulateHostFunctionResult>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub state_changes: Option<Vec<LedgerEntryChange>>,
+    /// Mismatches between the footprint of the simulated transaction and the
+    /// simulated one, only present when the transaction declared one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footprint_diagnostics: Option<FootprintDiagnostics>,
+    pub trace: InvocationTrace,
+    pub profile: CostProfile,
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SimulateTransactionErrorResponse {
-    pub id: String,
     pub latest_ledger: u32,
-    pub events: Vec<DiagnosticEvent>,
-    #[serde(rename = "_parsed")]
-    pub parsed: bool,
+    /// Base64 `DiagnosticEvent` XDR.
+    pub events: Vec<String>,
     pub error: String,
+    pub trace: InvocationTrace,
+    pub profile: CostProfile,
+}
+
+/// Budget spent by an invocation per cost type, along with the ledger entries
+/// it read and wrote.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostProfile {
+    pub cpu_insns: u64,
+    pub mem_bytes: u64,
+    /// Charged cost types, the most CPU consuming first.
+    pub cost_types: Vec<CostTypeUsage>,
+    pub read_entries: u32,
+    pub read_bytes: u32,
+    pub write_entries: u32,
+    pub write_bytes: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostTypeUsage {
+    /// Name of the `ContractCostType`, e.g. `WasmInsnExec`.
+    pub cost_type: String,
+    pub iterations: u64,
+    /// Sum of the inputs of the charges, for the cost types with a linear
+    /// term.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<u64>,
+    pub cpu_insns: u64,
+    pub mem_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationCost {
+    pub cpu_insns: String,
+    pub mem_bytes: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerEntryChangeType {
+    Created,
+    Updated,
+    Deleted,
 }
 
+/// Change of a ledger entry, with the key and entries as base64 XDR.
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LedgerEntryChange {
     #[serde(rename = "type")]
     pub change_type: LedgerEntryChangeType,
-    pub key: LedgerKey,
-    pub before: Option<LedgerEntry>,
-    pub after: Option<LedgerEntry>,
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SimulateHostFunctionResult {
     pub auth: Vec<String>,
-    pub retval: String,
+    pub xdr: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthMode {
+    #[default]
+    Recording,
+    Enforcing,
+    MockAll,
+}
+
+impl FromStr for AuthMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "recording" => Ok(Self::Recording),
+            "enforcing" => Ok(Self::Enforcing),
+            "mock_all" => Ok(Self::MockAll),
+            _ => anyhow::bail!("Invalid auth mode: {s}"),
+        }
+    }
+}
+
+impl From<AuthMode> for RecordingInvocationAuthMode {
+    fn from(value: AuthMode) -> Self {
+        match value {
+            AuthMode::Recording => RecordingInvocationAuthMode::Recording(true),
+            AuthMode::Enforcing => RecordingInvocationAuthMode::Enforcing(vec![]),
+            AuthMode::MockAll => RecordingInvocationAuthMode::Recording(false),
+        }
+    }
+}
+
+/// Authorization mode of `simulateTransaction`, named as in stellar-rpc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulationAuthMode {
+    /// Authorization entries of the transaction are enforced.
+    Enforce,
+    /// Root authorizations are recorded.
+    Record,
+    /// Non-root authorizations are recorded as well.
+    RecordAllowNonroot,
+}
+
+impl FromStr for SimulationAuthMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enforce" => Ok(Self::Enforce),
+            "record" => Ok(Self::Record),
+            "record_allow_nonroot" => Ok(Self::RecordAllowNonroot),
+            _ => anyhow::bail!("Invalid simulation auth mode: {s}"),
+        }
+    }
+}
+
+/// State a simulation runs against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SimulationState {
+    #[default]
+    Latest,
+    /// State at the end of a ledger of the history.
+    Ledger(u32),
+    /// State saved with `Sandbox::snapshot`.
+    Snapshot(u32),
+}
+
+/// Ledger entry changes applied for a single simulation only.
+#[derive(Debug, Clone, Default)]
+pub struct StateOverrides {
+    /// Entries to write, with their live until ledger.
+    pub entries: Vec<(LedgerEntry, Option<u32>)>,
+    /// Keys of the entries treated as absent.
+    pub removed: Vec<LedgerKey>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimulateOptions {
+    /// Defaults to `Enforce` when the transaction carries authorization
+    /// entries and to `Record` otherwise.
+    pub auth_mode: Option<SimulationAuthMode>,
+    /// Instructions added on top of the simulated ones instead of the default
+    /// adjustment.
+    pub instruction_leeway: Option<u32>,
+    pub state: SimulationState,
+    /// Applied on top of `state`.
+    pub overrides: Option<StateOverrides>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvokeResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retval: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub events: Vec<String>,
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+    pub auth: Vec<String>,
+    pub cpu_insns: u64,
+    pub mem_bytes: u64,
+    pub profile: CostProfile,
+    pub state_changes: Vec<LedgerEntryChange>,
 }
 
 #[derive(Serialize)]
@@ -101,6 +315,27 @@ pub enum Durability {
     Persistent,
 }
 
+impl FromStr for Durability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "temporary" => Ok(Self::Temporary),
+            "persistent" => Ok(Self::Persistent),
+            _ => anyhow::bail!("Invalid durability: {s}"),
+        }
+    }
+}
+
+impl From<Durability> for ContractDataDurability {
+    fn from(value: Durability) -> Self {
+        match value {
+            Durability::Temporary => ContractDataDurability::Temporary,
+            Durability::Persistent => ContractDataDurability::Persistent,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SendTransactionStatus {
@@ -128,7 +363,42 @@ pub struct SendTransactionResponse {
     pub error_result: Option<TransactionResult>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub diagnostic_events: Option<Vec<DiagnosticEvent>>,
+    /// Authorizations the invocation required, only present when auths were
+    /// mocked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_auth: Option<Vec<String>>,
+    /// Mismatches between the declared footprint and the accessed keys, only
+    /// present when a failed invocation didn't stay within its footprint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footprint_diagnostics: Option<FootprintDiagnostics>,
+    /// Contract calls of the invocation, only present when it was executed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<InvocationTrace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<CostProfile>,
+}
+
+/// Differences between a declared footprint and the keys an invocation
+/// accessed, as base64 `LedgerKey` XDR.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FootprintDiagnostics {
+    /// Keys accessed but missing from the footprint.
+    pub accessed_outside_footprint: Vec<String>,
+    /// Keys in the footprint that were never accessed.
+    pub declared_not_accessed: Vec<String>,
+    /// Keys declared read-only but written to.
+    pub read_only_written: Vec<String>,
+}
+
+impl FootprintDiagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.accessed_outside_footprint.is_empty()
+            && self.declared_not_accessed.is_empty()
+            && self.read_only_written.is_empty()
+    }
 }
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum GetTransactionStatus {
@@ -162,8 +432,9 @@ pub struct GetFailedTransactionResponse {
     pub envelope_xdr: String,
     pub result_xdr: String,
     pub result_meta_xdr: String,
+    /// Base64 `DiagnosticEvent` XDR.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub diagnostic_events_xdr: Option<Vec<DiagnosticEvent>>,
+    pub diagnostic_events_xdr: Option<Vec<String>>,
     pub events: TransactionEvents,
 }
 
@@ -182,8 +453,9 @@ pub struct GetSuccessfulTransactionResponse {
     pub envelope_xdr: String,
     pub result_xdr: String,
     pub result_meta_xdr: String,
+    /// Base64 `DiagnosticEvent` XDR.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub diagnostic_events_xdr: Option<Vec<DiagnosticEvent>>,
+    pub diagnostic_events_xdr: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub return_value: Option<Vec<u8>>,
     pub events: TransactionEvents,
@@ -206,3 +478,39 @@ pub struct TransactionEvents {
     pub transaction_events_xdr: Vec<TransactionEvent>,
     pub contract_events_xdr: Vec<Vec<ContractEvent>>,
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionEventsXdr {
+    pub transaction_events_xdr: Vec<String>,
+    pub contract_events_xdr: Vec<Vec<String>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionDetails {
+    pub status: GetTransactionStatus,
+    pub tx_hash: String,
+    pub application_order: u32,
+    pub fee_bump: bool,
+    pub envelope_xdr: String,
+    pub result_xdr: String,
+    pub result_meta_xdr: String,
+    /// Base64 `DiagnosticEvent` XDR.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostic_events_xdr: Option<Vec<String>>,
+    pub ledger: u32,
+    pub created_at: u64,
+    pub events: TransactionEventsXdr,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTransactionsResponse {
+    pub transactions: Vec<TransactionDetails>,
+    pub latest_ledger: u32,
+    pub latest_ledger_close_timestamp: u64,
+    pub oldest_ledger: u32,
+    pub oldest_ledger_close_timestamp: u64,
+    pub cursor: String,
+}
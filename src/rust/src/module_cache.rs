@@ -1,11 +1,23 @@
-use anyhow::anyhow;
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::{Mutex, PoisonError},
+};
+
+use anyhow::{anyhow, Context};
 use soroban_env_common::Val;
 use soroban_env_host::{
     budget::{AsBudget, Budget},
-    xdr::ContractCostParams,
+    vm::VersionedContractCodeCostInputs,
+    xdr::{
+        ContractCodeEntry, ContractCodeEntryExt, ContractCostParams, Hash, LedgerEntry,
+        LedgerEntryData,
+    },
     CompilationContext, ErrorHandler, HostError, ModuleCache,
 };
 
+use crate::utils::is_content_addressed;
+
 pub fn new_module_cache() -> anyhow::Result<(ModuleCache, CoreCompilationContext)> {
     let ctx =
         CoreCompilationContext::new().map_err(|e| anyhow!("error creating module cache: {}", e))?;
@@ -14,6 +26,109 @@ pub fn new_module_cache() -> anyhow::Result<(ModuleCache, CoreCompilationContext
     Ok((cache, ctx))
 }
 
+/// Compiled modules kept across transactions, a cache per protocol version
+/// keyed by Wasm hash. Only code matching its hash is cached, so a module is
+/// the same for every state that has its code and is never evicted. The
+/// compilation context holds a non thread-safe budget, so a new one is made
+/// for every compilation.
+#[derive(Default)]
+pub struct ModuleStore {
+    caches: Mutex<HashMap<u32, ModuleCache>>,
+}
+
+impl ModuleStore {
+    /// Returns a cache with the code entries among `ledger_entries` compiled.
+    /// Restored contracts are left out, so the host parses and charges them
+    /// like the network does for archived code. The shared cache has every
+    /// other module, so a cache of their own is made for the invocations that
+    /// restore code or run code that doesn't match its hash.
+    pub fn cache_for_entries(
+        &self,
+        protocol_version: u32,
+        ledger_entries: &[(Rc<LedgerEntry>, Option<u32>)],
+        restored_contracts: &HashSet<Hash>,
+    ) -> anyhow::Result<ModuleCache> {
+        let codes: Vec<_> = ledger_entries
+            .iter()
+            .filter_map(|(entry, _)| match &entry.data {
+                LedgerEntryData::ContractCode(code) => Some(code),
+                _ => None,
+            })
+            .collect();
+        let shared = codes
+            .iter()
+            .all(|code| !restored_contracts.contains(&code.hash) && is_content_addressed(code));
+
+        let cache = match shared {
+            true => self.cache(protocol_version)?,
+            false => {
+                let (cache, _) = new_module_cache().context("Failed to create new module cache")?;
+                cache
+            },
+        };
+        for code in codes {
+            if !restored_contracts.contains(&code.hash) {
+                compile(&cache, protocol_version, code)?;
+            }
+        }
+
+        Ok(cache)
+    }
+
+    /// Compiles the code into the shared cache unless it's already there or
+    /// doesn't match its hash.
+    pub fn add(&self, protocol_version: u32, code: &ContractCodeEntry) -> anyhow::Result<()> {
+        if is_content_addressed(code) {
+            compile(&self.cache(protocol_version)?, protocol_version, code)?;
+        }
+
+        Ok(())
+    }
+
+    fn cache(&self, protocol_version: u32) -> anyhow::Result<ModuleCache> {
+        let mut caches = self.caches.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(cache) = caches.get(&protocol_version) {
+            return Ok(cache.clone());
+        }
+
+        let (cache, _) = new_module_cache().context("Failed to create new module cache")?;
+        caches.insert(protocol_version, cache.clone());
+
+        Ok(cache)
+    }
+}
+
+/// Compiles the code unless a module with its hash is already cached.
+fn compile(
+    cache: &ModuleCache,
+    protocol_version: u32,
+    code: &ContractCodeEntry,
+) -> anyhow::Result<()> {
+    if cache.contains_module(&code.hash)? {
+        return Ok(());
+    }
+
+    let ctx = CoreCompilationContext::new()
+        .map_err(|e| anyhow!("error creating compilation context: {}", e))?;
+    let code_cost_inputs = match &code.ext {
+        ContractCodeEntryExt::V0 => VersionedContractCodeCostInputs::V0 {
+            wasm_bytes: code.code.len(),
+        },
+        ContractCodeEntryExt::V1(v1) => VersionedContractCodeCostInputs::V1(v1.cost_inputs.clone()),
+    };
+    cache
+        .parse_and_cache_module(
+            &ctx,
+            protocol_version,
+            &code.hash,
+            &code.code,
+            code_cost_inputs,
+        )
+        .context("Failed to parse and cache module")?;
+
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct CoreCompilationContext {
     unlimited_budget: Budget,
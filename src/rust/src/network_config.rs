@@ -1,4 +1,4 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use soroban_env_host::{
     e2e_testutils::ledger_entry,
@@ -18,42 +18,93 @@ use crate::{ledger_info::get_initial_ledger_info, memory::Memory};
 fn _config_entry(entry: ConfigSettingEntry) -> (LedgerEntry, Option<u32>) {
     (ledger_entry(LedgerEntryData::ConfigSetting(entry)), None)
 }
+/// Cost models of the host for every `ContractCostType`, in order, as
+/// `(cpu const, cpu linear, mem const, mem linear)` terms. Linear terms are
+/// fixed point numbers with 7 fractional bits, like in the network settings.
+const COST_PARAMS: [(i64, i64, i64, i64); 70] = [
+    (4, 0, 0, 0),                         // WasmInsnExec
+    (434, 16, 16, 128),                   // MemAlloc
+    (42, 16, 0, 0),                       // MemCpy
+    (44, 16, 0, 0),                       // MemCmp
+    (310, 0, 0, 0),                       // DispatchHostFunction
+    (61, 0, 0, 0),                        // VisitObject
+    (230, 29, 242, 384),                  // ValSer
+    (59052, 4001, 0, 384),                // ValDeser
+    (3738, 7012, 0, 0),                   // ComputeSha256Hash
+    (40253, 0, 0, 0),                     // ComputeEd25519PubKey
+    (377524, 4068, 0, 0),                 // VerifyEd25519Sig
+    (451626, 45405, 130065, 5064),        // VmInstantiation
+    (41142, 634, 69472, 1217),            // VmCachedInstantiation
+    (1948, 0, 14, 0),                     // InvokeVmFunction
+    (3766, 5969, 0, 0),                   // ComputeKeccak256Hash
+    (710, 0, 0, 0),                       // DecodeEcdsaCurve256Sig
+    (2315295, 0, 181, 0),                 // RecoverEcdsaSecp256k1Key
+    (4404, 0, 99, 0),                     // Int256AddSub
+    (4947, 0, 99, 0),                     // Int256Mul
+    (4911, 0, 99, 0),                     // Int256Div
+    (4286, 0, 99, 0),                     // Int256Pow
+    (913, 0, 99, 0),                      // Int256Shift
+    (1058, 501, 0, 0),                    // ChaCha20DrawBytes
+    (73077, 25410, 17564, 6457),          // ParseWasmInstructions
+    (0, 540752, 0, 47464),                // ParseWasmFunctions
+    (0, 176363, 0, 13420),                // ParseWasmGlobals
+    (0, 29989, 0, 6285),                  // ParseWasmTableEntries
+    (0, 1061449, 0, 64670),               // ParseWasmTypes
+    (0, 237336, 0, 29074),                // ParseWasmDataSegments
+    (0, 328476, 0, 48095),                // ParseWasmElemSegments
+    (0, 701845, 0, 103229),               // ParseWasmImports
+    (0, 429383, 0, 36394),                // ParseWasmExports
+    (0, 28, 0, 257),                      // ParseWasmDataSegmentBytes
+    (43030, 0, 70704, 0),                 // InstantiateWasmInstructions
+    (0, 7556, 0, 14613),                  // InstantiateWasmFunctions
+    (0, 10711, 0, 6833),                  // InstantiateWasmGlobals
+    (0, 3300, 0, 1025),                   // InstantiateWasmTableEntries
+    (0, 0, 0, 0),                         // InstantiateWasmTypes
+    (0, 23038, 0, 129632),                // InstantiateWasmDataSegments
+    (0, 42488, 0, 13665),                 // InstantiateWasmElemSegments
+    (0, 828974, 0, 97637),                // InstantiateWasmImports
+    (0, 297100, 0, 9176),                 // InstantiateWasmExports
+    (0, 14, 0, 126),                      // InstantiateWasmDataSegmentBytes
+    (1882, 0, 0, 0),                      // Sec1DecodePointUncompressed
+    (3000906, 0, 0, 0),                   // VerifyEcdsaSecp256r1Sig
+    (661, 0, 0, 0),                       // Bls12381EncodeFp
+    (985, 0, 0, 0),                       // Bls12381DecodeFp
+    (1934, 0, 0, 0),                      // Bls12381G1CheckPointOnCurve
+    (730510, 0, 0, 0),                    // Bls12381G1CheckPointInSubgroup
+    (5921, 0, 0, 0),                      // Bls12381G2CheckPointOnCurve
+    (1057822, 0, 0, 0),                   // Bls12381G2CheckPointInSubgroup
+    (92642, 0, 0, 0),                     // Bls12381G1ProjectiveToAffine
+    (100742, 0, 0, 0),                    // Bls12381G2ProjectiveToAffine
+    (7689, 0, 0, 0),                      // Bls12381G1Add
+    (2458985, 0, 0, 0),                   // Bls12381G1Mul
+    (2426722, 96397671, 109494, 354667),  // Bls12381G1Msm
+    (1541554, 0, 5552, 0),                // Bls12381MapFpToG1
+    (3211191, 6713, 9424, 0),             // Bls12381HashToG1
+    (25207, 0, 0, 0),                     // Bls12381G2Add
+    (7873219, 0, 0, 0),                   // Bls12381G2Mul
+    (8035968, 309667335, 219654, 354667), // Bls12381G2Msm
+    (2420202, 0, 3344, 0),                // Bls12381MapFp2ToG2
+    (7050564, 6797, 6816, 0),             // Bls12381HashToG2
+    (10558948, 632860943, 2204, 9340474), // Bls12381Pairing
+    (1994, 0, 0, 0),                      // Bls12381FrFromU256
+    (1155, 0, 248, 0),                    // Bls12381FrToU256
+    (74, 0, 0, 0),                        // Bls12381FrAddSub
+    (332, 0, 0, 0),                       // Bls12381FrMul
+    (691, 74558, 0, 128),                 // Bls12381FrPow
+    (35421, 0, 0, 0),                     // Bls12381FrInv
+];
+
 pub fn default_network_config() -> anyhow::Result<NetworkConfig> {
-    let cpu_cost_params = ContractCostParams(
-        vec![
-            ContractCostParamEntry {
-                ext: ExtensionPoint::V0,
-                const_term: 35,
-                linear_term: 36,
-            },
-            ContractCostParamEntry {
-                ext: ExtensionPoint::V0,
-                const_term: 37,
-                linear_term: 38,
-            },
-        ]
-        .try_into()?,
-    );
-    let mem_cost_params = ContractCostParams(
-        vec![
-            ContractCostParamEntry {
-                ext: ExtensionPoint::V0,
-                const_term: 39,
-                linear_term: 40,
-            },
-            ContractCostParamEntry {
-                ext: ExtensionPoint::V0,
-                const_term: 41,
-                linear_term: 42,
-            },
-            ContractCostParamEntry {
-                ext: ExtensionPoint::V0,
-                const_term: 43,
-                linear_term: 44,
-            },
-        ]
-        .try_into()?,
-    );
+    let cpu_cost_params = cost_params(
+        COST_PARAMS
+            .iter()
+            .map(|(cpu, linear, _, _)| (*cpu, *linear)),
+    )?;
+    let mem_cost_params = cost_params(
+        COST_PARAMS
+            .iter()
+            .map(|(_, _, mem, linear)| (*mem, *linear)),
+    )?;
 
     let ledger_info = get_initial_ledger_info();
 
@@ -77,15 +128,50 @@ pub fn default_network_config() -> anyhow::Result<NetworkConfig> {
         },
         tx_max_instructions: 100_000_000,
         tx_memory_limit: 40_000_000,
-        cpu_cost_params: ContractCostParams(cpu_cost_params.into()),
-        memory_cost_params: ContractCostParams(mem_cost_params.into()),
+        cpu_cost_params,
+        memory_cost_params: mem_cost_params,
         min_temp_entry_ttl: ledger_info.min_temp_entry_ttl,
         min_persistent_entry_ttl: ledger_info.min_persistent_entry_ttl,
         max_entry_ttl: ledger_info.max_entry_ttl,
     })
 }
 
-pub fn _populate_memory_with_config_entries(memory: Rc<Memory>) {
+fn cost_params(terms: impl Iterator<Item = (i64, i64)>) -> anyhow::Result<ContractCostParams> {
+    let entries = terms
+        .map(|(const_term, linear_term)| ContractCostParamEntry {
+            ext: ExtensionPoint::V0,
+            const_term,
+            linear_term,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(ContractCostParams(entries.try_into()?))
+}
+
+/// Per transaction Soroban limits, the same as on pubnet.
+pub struct SorobanTxLimits {
+    pub tx_max_instructions: u32,
+    pub tx_max_disk_read_entries: u32,
+    pub tx_max_disk_read_bytes: u32,
+    pub tx_max_write_ledger_entries: u32,
+    pub tx_max_write_bytes: u32,
+    pub tx_max_footprint_entries: u32,
+    pub tx_max_size_bytes: u32,
+}
+
+pub fn default_tx_limits() -> SorobanTxLimits {
+    SorobanTxLimits {
+        tx_max_instructions: 100_000_000,
+        tx_max_disk_read_entries: 100,
+        tx_max_disk_read_bytes: 200_000,
+        tx_max_write_ledger_entries: 50,
+        tx_max_write_bytes: 132_096,
+        tx_max_footprint_entries: 100,
+        tx_max_size_bytes: 132_096,
+    }
+}
+
+pub fn _populate_memory_with_config_entries(memory: Arc<Memory>) {
     let cpu_cost_params = ContractCostParams(
         vec![
             ContractCostParamEntry {
@@ -200,3 +286,49 @@ pub fn _populate_memory_with_config_entries(memory: Rc<Memory>) {
         memory.insert_with_ttl(entry, ttl);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use soroban_env_host::{budget::Budget, xdr::ContractCostType};
+
+    use super::*;
+
+    /// Budget spent by charging `ty` once.
+    fn charge(budget: Budget, ty: ContractCostType, input: Option<u64>) -> (u64, u64) {
+        budget.reset_unlimited().unwrap();
+        budget.charge(ty, input).unwrap();
+
+        (
+            budget.get_cpu_insns_consumed().unwrap(),
+            budget.get_mem_bytes_consumed().unwrap(),
+        )
+    }
+
+    #[test]
+    fn matches_the_cost_models_of_the_host() {
+        let config = default_network_config().unwrap();
+        let configured = || {
+            Budget::try_from_configs(
+                0,
+                0,
+                config.cpu_cost_params.clone(),
+                config.memory_cost_params.clone(),
+            )
+            .unwrap()
+        };
+
+        for ty in ContractCostType::variants() {
+            let input = Budget::default()
+                .get_tracker(ty)
+                .unwrap()
+                .inputs
+                .map(|_| 1000);
+            assert_eq!(
+                charge(configured(), ty, input),
+                charge(Budget::default(), ty, input),
+                "{}",
+                ty.name()
+            );
+        }
+    }
+}
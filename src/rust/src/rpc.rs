@@ -0,0 +1,686 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use soroban_env_common::xdr::{
+    AccountId, ContractEventBody, ContractEventType, LedgerEntryData, LedgerKey, Limits, ReadXdr,
+    ScAddress, TransactionEnvelope, WriteXdr,
+};
+use soroban_env_host::e2e_testutils::ledger_entry;
+
+use crate::{
+    ledger_info::NETWORK_PASSPHRASE,
+    model::{GetTransactionResponse, SimulateOptions, SimulationAuthMode},
+    sandbox::{toid, Sandbox},
+};
+
+const DEFAULT_EVENTS_LIMIT: usize = 100;
+const MAX_EVENTS_LIMIT: usize = 10_000;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GetLedgerEntriesParams {
+    keys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct TransactionParams {
+    transaction: String,
+}
+
+impl TransactionParams {
+    fn envelope(&self) -> Result<TransactionEnvelope> {
+        TransactionEnvelope::from_xdr_base64(&self.transaction, Limits::none())
+            .context("invalid transaction envelope")
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SimulateTransactionParams {
+    #[serde(flatten)]
+    transaction: TransactionParams,
+    auth_mode: Option<SimulationAuthMode>,
+    #[serde(default)]
+    resource_config: ResourceConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceConfig {
+    instruction_leeway: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct GetTransactionParams {
+    hash: String,
+}
+
+/// Params of the paginated `getLedgers` and `getTransactions` methods.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetLedgersParams {
+    start_ledger: Option<u32>,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetEventsParams {
+    start_ledger: Option<u32>,
+    #[serde(default)]
+    filters: Vec<EventFilter>,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventFilter {
+    #[serde(rename = "type")]
+    event_type: Option<String>,
+    #[serde(default)]
+    contract_ids: Vec<String>,
+    #[serde(default)]
+    topics: Vec<Vec<String>>,
+}
+
+#[derive(Deserialize, Default)]
+struct Pagination {
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EventInfo {
+    #[serde(rename = "type")]
+    event_type: String,
+    ledger: u32,
+    ledger_closed_at: String,
+    contract_id: String,
+    id: String,
+    paging_token: String,
+    topic: Vec<String>,
+    value: String,
+    in_successful_contract_call: bool,
+    tx_hash: String,
+}
+
+/// Serves the stellar-rpc JSON-RPC methods on top of a [`Sandbox`], so that any
+/// Stellar client can talk to it over HTTP.
+pub struct RpcHandler {
+    sandbox: Sandbox,
+}
+
+impl Default for RpcHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Sandbox> for RpcHandler {
+    fn from(sandbox: Sandbox) -> Self {
+        Self { sandbox }
+    }
+}
+
+impl RpcHandler {
+    pub fn new() -> Self {
+        Self {
+            sandbox: Sandbox::new(),
+        }
+    }
+
+    /// Funds a `G...` account, backs the friendbot endpoint of the server.
+    /// Existing accounts only get `balance` added, their sequence number and
+    /// signers are kept.
+    pub fn fund_account(&self, address: &str, balance: i64) -> Result<()> {
+        let account_id = AccountId::from_str(address).context("invalid account address")?;
+
+        let Ok(mut account) = self.sandbox.get_account(account_id.clone()) else {
+            return self.sandbox.fund_account(account_id, balance);
+        };
+        account.balance = account
+            .balance
+            .checked_add(balance)
+            .context("account balance overflow")?;
+
+        self.sandbox
+            .set_ledger_entry(ledger_entry(LedgerEntryData::Account(account)), None)
+    }
+
+    /// Handles a single JSON-RPC 2.0 request body and returns the response
+    /// body.
+    pub fn handle(&mut self, body: &str) -> String {
+        let request: Request = match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(e) => return error_response(Value::Null, RpcError::new(PARSE_ERROR, e)),
+        };
+
+        if request.jsonrpc != "2.0" {
+            return error_response(
+                request.id,
+                RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+            );
+        }
+
+        match self.dispatch(&request.method, request.params) {
+            Ok(result) => {
+                json!({ "jsonrpc": "2.0", "id": request.id, "result": result }).to_string()
+            },
+            Err(e) => error_response(request.id, e),
+        }
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let result = match method {
+            "getHealth" => self.get_health(),
+            "getNetwork" => self.get_network(),
+            "getVersionInfo" => self.get_version_info(),
+            "getLatestLedger" => self.get_latest_ledger(),
+            "getLedgers" => self.get_ledgers(parse_params(params)?),
+            "getLedgerEntries" => self.get_ledger_entries(parse_params(params)?),
+            "simulateTransaction" => self.simulate_transaction(parse_params(params)?),
+            "sendTransaction" => self.send_transaction(parse_params(params)?),
+            "getTransaction" => self.get_transaction(parse_params(params)?),
+            "getTransactions" => self.get_transactions(parse_params(params)?),
+            "getEvents" => self.get_events(parse_params(params)?),
+            _ => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("method not found: {method}"),
+                ))
+            },
+        };
+
+        result.map_err(|e| RpcError::new(INTERNAL_ERROR, format!("{e:#}")))
+    }
+
+    fn get_health(&self) -> Result<Value> {
+        Ok(serde_json::to_value(self.sandbox.get_health())?)
+    }
+
+    fn get_version_info(&self) -> Result<Value> {
+        Ok(serde_json::to_value(self.sandbox.get_version_info())?)
+    }
+
+    fn get_network(&self) -> Result<Value> {
+        Ok(json!({
+            "passphrase": NETWORK_PASSPHRASE,
+            "protocolVersion": self.sandbox.get_ledger_info().protocol_version,
+        }))
+    }
+
+    fn get_latest_ledger(&self) -> Result<Value> {
+        Ok(serde_json::to_value(self.sandbox.get_latest_ledger())?)
+    }
+
+    fn get_ledgers(&self, params: GetLedgersParams) -> Result<Value> {
+        let response = self.sandbox.get_ledgers(
+            params.start_ledger,
+            params.pagination.cursor,
+            params.pagination.limit,
+        )?;
+
+        Ok(serde_json::to_value(response)?)
+    }
+
+    fn get_ledger_entries(&self, params: GetLedgerEntriesParams) -> Result<Value> {
+        let entries = self
+            .sandbox
+            .get_ledger_entries(
+                params
+                    .keys
+                    .iter()
+                    .map(|key| LedgerKey::from_xdr_base64(key, Limits::none()))
+                    .collect::<Result<_, _>>()
+                    .context("Invalid key XDR")?,
+            )?
+            .into_iter()
+            .map(|entry| {
+                let mut value = json!({
+                    "key": entry.key,
+                    "xdr": entry.val,
+                    "lastModifiedLedgerSeq": entry.last_modified_ledger_seq,
+                });
+                if let Some(live_until) = entry.live_until_ledger_seq {
+                    value["liveUntilLedgerSeq"] = live_until.into();
+                }
+
+                value
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "entries": entries,
+            "latestLedger": self.sandbox.get_ledger_info().sequence_number,
+        }))
+    }
+
+    fn simulate_transaction(&self, params: SimulateTransactionParams) -> Result<Value> {
+        let response = self.sandbox.simulate_transaction(
+            params.transaction.envelope()?,
+            SimulateOptions {
+                auth_mode: params.auth_mode,
+                instruction_leeway: params.resource_config.instruction_leeway,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(serde_json::to_value(response)?)
+    }
+
+    fn send_transaction(&mut self, params: TransactionParams) -> Result<Value> {
+        let response = self.sandbox.send_transaction(params.envelope()?, None)?;
+
+        let mut value = serde_json::to_value(&response.base)?;
+        if let Some(error_result) = response.error_result {
+            value["errorResultXdr"] = error_result.to_xdr_base64(Limits::none())?.into();
+        }
+        if let Some(events) = response.diagnostic_events {
+            value["diagnosticEventsXdr"] = events_to_xdr(&events)?.into();
+        }
+        if let Some(diagnostics) = response.footprint_diagnostics {
+            value["footprintDiagnostics"] = serde_json::to_value(diagnostics)?;
+        }
+        if let Some(trace) = response.trace {
+            value["trace"] = serde_json::to_value(trace)?;
+        }
+        if let Some(profile) = response.profile {
+            value["profile"] = serde_json::to_value(profile)?;
+        }
+
+        Ok(value)
+    }
+
+    fn get_transaction(&self, params: GetTransactionParams) -> Result<Value> {
+        let response = self.sandbox.get_transaction(params.hash)?;
+        let events = match &response {
+            GetTransactionResponse::Success(success) => Some(&success.events),
+            GetTransactionResponse::Failed(failed) => Some(&failed.events),
+            GetTransactionResponse::NotFound(_) => None,
+        };
+
+        let events = events
+            .map(|events| -> Result<Value> {
+                Ok(json!({
+                    "transactionEventsXdr": events
+                        .transaction_events_xdr
+                        .iter()
+                        .map(|e| e.to_xdr_base64(Limits::none()))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    "contractEventsXdr": events
+                        .contract_events_xdr
+                        .iter()
+                        .map(|op_events| {
+                            op_events
+                                .iter()
+                                .map(|e| e.to_xdr_base64(Limits::none()))
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                }))
+            })
+            .transpose()?;
+
+        let mut value = serde_json::to_value(&response)?;
+        if let (Some(object), Some(events)) = (value.as_object_mut(), events) {
+            // The return value is only exposed through the result meta.
+            object.remove("returnValue");
+            object.insert("events".into(), events);
+        }
+
+        Ok(value)
+    }
+
+    fn get_transactions(&self, params: GetLedgersParams) -> Result<Value> {
+        let response = self.sandbox.get_transactions(
+            params.start_ledger,
+            params.pagination.cursor,
+            params.pagination.limit,
+        )?;
+
+        Ok(serde_json::to_value(response)?)
+    }
+
+    fn get_events(&self, params: GetEventsParams) -> Result<Value> {
+        let latest = self.sandbox.ledger_history().latest();
+        let oldest = self.sandbox.ledger_history().oldest();
+        let limit = params
+            .pagination
+            .limit
+            .map_or(DEFAULT_EVENTS_LIMIT, |limit| limit as usize)
+            .min(MAX_EVENTS_LIMIT);
+
+        let start_ledger = match (&params.pagination.cursor, params.start_ledger) {
+            (Some(_), _) => 0,
+            (None, Some(start_ledger)) => start_ledger,
+            (None, None) => bail!("startLedger must be provided when no cursor is given"),
+        };
+
+        let mut events = Vec::new();
+
+        'transactions: for (hash, ti) in self.sandbox.transactions() {
+            let ledger = ti.ledger_info.sequence_number;
+            if ledger < start_ledger {
+                continue;
+            }
+
+            for (index, event) in ti.events.iter().enumerate() {
+                let event = &event.event;
+                let event_type = match event.type_ {
+                    ContractEventType::Contract => "contract",
+                    ContractEventType::System => "system",
+                    ContractEventType::Diagnostic => continue,
+                };
+
+                let id = event_id(ledger, ti.application_order, index);
+                if params
+                    .pagination
+                    .cursor
+                    .as_ref()
+                    .is_some_and(|cursor| id.as_str() <= cursor.as_str())
+                {
+                    continue;
+                }
+
+                let ContractEventBody::V0(body) = &event.body;
+                let contract_id = event
+                    .contract_id
+                    .clone()
+                    .map(|id| ScAddress::Contract(id).to_string())
+                    .unwrap_or_default();
+                let topic = body
+                    .topics
+                    .iter()
+                    .map(|t| t.to_xdr_base64(Limits::none()))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let matches = params.filters.is_empty()
+                    || params
+                        .filters
+                        .iter()
+                        .any(|filter| filter.matches(event_type, &contract_id, &topic));
+                if !matches {
+                    continue;
+                }
+
+                events.push(EventInfo {
+                    event_type: event_type.into(),
+                    ledger,
+                    ledger_closed_at: format_timestamp(ti.ledger_info.timestamp),
+                    contract_id,
+                    paging_token: id.clone(),
+                    id,
+                    topic,
+                    value: body.data.to_xdr_base64(Limits::none())?,
+                    in_successful_contract_call: ti.result.is_ok(),
+                    tx_hash: hash.to_string(),
+                });
+
+                if events.len() == limit {
+                    break 'transactions;
+                }
+            }
+        }
+
+        let cursor = events
+            .last()
+            .map(|e| e.id.clone())
+            .or(params.pagination.cursor)
+            .unwrap_or_default();
+
+        Ok(json!({
+            "events": events,
+            "cursor": cursor,
+            "latestLedger": latest.sequence,
+            "latestLedgerCloseTime": latest.close_time.to_string(),
+            "oldestLedger": oldest.sequence,
+            "oldestLedgerCloseTime": oldest.close_time.to_string(),
+        }))
+    }
+}
+
+impl EventFilter {
+    fn matches(&self, event_type: &str, contract_id: &str, topic: &[String]) -> bool {
+        if self.event_type.as_deref().is_some_and(|t| t != event_type) {
+            return false;
+        }
+        if !self.contract_ids.is_empty() && !self.contract_ids.iter().any(|id| id == contract_id) {
+            return false;
+        }
+
+        self.topics.is_empty()
+            || self
+                .topics
+                .iter()
+                .any(|segments| topic_matches(segments, topic))
+    }
+}
+
+/// Matches event topics against a filter, `*` matches exactly one topic and a
+/// trailing `**` matches any number of remaining topics.
+fn topic_matches(segments: &[String], topic: &[String]) -> bool {
+    for (i, segment) in segments.iter().enumerate() {
+        if segment == "**" {
+            return true;
+        }
+        match topic.get(i) {
+            Some(t) if segment == "*" || segment == t => {},
+            _ => return false,
+        }
+    }
+
+    segments.len() == topic.len()
+}
+
+/// Formats an event id the way stellar-rpc does: the TOID of the operation
+/// followed by the index of the event within it.
+fn event_id(ledger: u32, application_order: u32, index: usize) -> String {
+    let toid = toid(ledger, application_order) | 1;
+
+    format!("{toid:019}-{index:010}")
+}
+
+/// Formats a unix timestamp as an RFC 3339 UTC date.
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    // Civil date from days since the unix epoch, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn events_to_xdr<T: WriteXdr>(events: &[T]) -> Result<Vec<String>> {
+    events
+        .iter()
+        .map(|e| e.to_xdr_base64(Limits::none()))
+        .collect::<Result<Vec<_>, _>>()
+        .context("event serialization failed")
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+fn error_response(id: Value, error: RpcError) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use soroban_env_common::xdr::{TransactionResult, TransactionResultResult};
+
+    use super::*;
+    use crate::test_utils::{bump_sequence, Key};
+
+    fn call(handler: &mut RpcHandler, method: &str, params: Value) -> Value {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+
+        serde_json::from_str(&handler.handle(&body.to_string())).unwrap()
+    }
+
+    fn error_code(response: &Value) -> i64 {
+        response["error"]["code"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn dispatches_methods() {
+        let mut handler = RpcHandler::new();
+
+        let health = call(&mut handler, "getHealth", Value::Null);
+        assert_eq!(health["id"], 1);
+        assert_eq!(health["result"]["status"], "healthy");
+
+        let network = call(&mut handler, "getNetwork", Value::Null);
+        assert_eq!(network["result"]["passphrase"], NETWORK_PASSPHRASE);
+
+        let latest = call(&mut handler, "getLatestLedger", Value::Null);
+        let sequence = &latest["result"]["sequence"];
+        let params = json!({ "startLedger": sequence, "pagination": { "limit": 1 } });
+        let ledgers = call(&mut handler, "getLedgers", params);
+        assert_eq!(ledgers["result"]["ledgers"][0]["sequence"], *sequence);
+
+        let unknown = call(&mut handler, "getFoo", Value::Null);
+        assert_eq!(error_code(&unknown), METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let mut handler = RpcHandler::new();
+
+        let response: Value = serde_json::from_str(&handler.handle("{")).unwrap();
+        assert_eq!(error_code(&response), PARSE_ERROR);
+        assert_eq!(response["id"], Value::Null);
+
+        let body = json!({ "jsonrpc": "1.0", "id": 2, "method": "getHealth" });
+        let response: Value = serde_json::from_str(&handler.handle(&body.to_string())).unwrap();
+        assert_eq!(error_code(&response), INVALID_REQUEST);
+        assert_eq!(response["id"], 2);
+    }
+
+    #[test]
+    fn rejects_bad_params() {
+        let mut handler = RpcHandler::new();
+
+        let missing = call(&mut handler, "getTransaction", json!({}));
+        assert_eq!(error_code(&missing), INVALID_PARAMS);
+
+        for method in ["getLedgers", "getTransactions", "getEvents"] {
+            let params = json!({ "startLedger": 1, "pagination": { "limit": 1u64 << 32 } });
+            let response = call(&mut handler, method, params);
+            assert_eq!(error_code(&response), INVALID_PARAMS, "{method}");
+        }
+
+        let envelope = call(
+            &mut handler,
+            "sendTransaction",
+            json!({ "transaction": "AAAA" }),
+        );
+        assert_eq!(error_code(&envelope), INTERNAL_ERROR);
+    }
+
+    #[test]
+    fn reports_rejected_transactions() {
+        let sandbox = Sandbox::new();
+        let key = Key::new(1);
+        sandbox.fund_account(key.account(), 1_000_000_000).unwrap();
+        let mut envelope = bump_sequence(&sandbox, &key);
+        envelope.tx.seq_num.0 += 1;
+        key.sign(&sandbox, &mut envelope);
+        let mut handler = RpcHandler::from(sandbox);
+
+        let transaction = TransactionEnvelope::Tx(envelope)
+            .to_xdr_base64(Limits::none())
+            .unwrap();
+        let response = call(
+            &mut handler,
+            "sendTransaction",
+            json!({ "transaction": transaction }),
+        );
+
+        assert_eq!(response["result"]["status"], "ERROR");
+        let result = TransactionResult::from_xdr_base64(
+            response["result"]["errorResultXdr"].as_str().unwrap(),
+            Limits::none(),
+        )
+        .unwrap();
+        assert_eq!(result.result, TransactionResultResult::TxBadSeq);
+    }
+
+    #[test]
+    fn matches_topics() {
+        let topic = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let event = topic(&["a", "b", "c"]);
+
+        assert!(topic_matches(&topic(&["a", "b", "c"]), &event));
+        assert!(topic_matches(&topic(&["a", "*", "c"]), &event));
+        assert!(topic_matches(&topic(&["a", "**"]), &event));
+        assert!(topic_matches(&topic(&["**"]), &event));
+        assert!(!topic_matches(&topic(&["a", "*"]), &event));
+        assert!(!topic_matches(&topic(&["a", "b", "c", "*"]), &event));
+        assert!(!topic_matches(&topic(&["a", "c", "**"]), &event));
+        assert!(!topic_matches(&topic(&["a", "b", "c", "d", "**"]), &event));
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1_709_251_199), "2024-02-29T23:59:59Z");
+        assert_eq!(format_timestamp(4_102_444_800), "2100-01-01T00:00:00Z");
+    }
+}
@@ -1,58 +1,152 @@
-use std::rc::Rc;
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
 
-use anyhow::{anyhow, bail, Context, Result};
-use napi::Error;
+use anyhow::{anyhow, bail, ensure, Result};
+use im::{OrdMap, Vector};
 use soroban_env_common::xdr::{
-    AccountEntry, AccountEntryExt, AccountId, LedgerEntry, LedgerEntryData, LedgerKey,
-    LedgerKeyAccount, Limits, OperationResultTr, ReadXdr, SequenceNumber, String32, Thresholds,
-    TransactionEnvelope, TransactionResultResult, TransactionV1Envelope,
+    AccountEntry, AccountEntryExt, AccountId, DiagnosticEvent, LedgerEntry, LedgerEntryData,
+    LedgerKey, LedgerKeyAccount, Limits, Operation, OperationBody, SequenceNumber, String32,
+    Thresholds, TransactionEnvelope, TransactionV1Envelope,
 };
 use soroban_env_host::{
+    e2e_invoke::RecordingInvocationAuthMode,
     e2e_testutils::ledger_entry,
+    ledger_info::get_key_durability,
     storage::SnapshotSource,
     xdr::{
-        ContractDataDurability, Hash, InvokeHostFunctionResult, LedgerKeyContractData,
-        OperationResult, ScAddress, ScVal, TransactionResult, WriteXdr,
+        ContractDataDurability, ContractDataEntry, ContractExecutable, ContractIdPreimage,
+        ContractIdPreimageFromAddress, CreateContractArgsV2, ExtensionPoint, Hash, HostFunction,
+        InvokeContractArgs, LedgerKeyContractData, PublicKey, ScAddress, ScVal, TransactionResult,
+        Uint256, WriteXdr,
     },
     LedgerInfo,
 };
 
 use crate::{
-    executor::{ExecutionResult, Executor},
+    cost_snapshot::{CostRecord, CostSnapshot},
+    executor::{AuthOverride, ExecutionResult, Executor},
+    ledger_history::LedgerHistory,
     ledger_info::{get_initial_ledger_info, NETWORK_PASSPHRASE},
     memory::Memory,
     model::{
-        BaseSendTransactionResponse, GetFailedTransactionResponse, GetMissingTransactionResponse,
-        GetSuccessfulTransactionResponse, GetTransactionResponse, LedgerEntryResult,
-        SendTransactionResponse, SendTransactionStatus, TransactionEvents,
+        AuthMode, BaseSendTransactionResponse, GetFailedTransactionResponse, GetHealthResponse,
+        GetLatestLedgerResponse, GetLedgersResponse, GetMissingTransactionResponse,
+        GetSuccessfulTransactionResponse, GetTransactionResponse, GetTransactionStatus,
+        GetTransactionsResponse, GetVersionInfoResponse, InvokeResponse, LedgerEntryResult,
+        LedgerResponse, NetworkInfo, SendTransactionResponse, SendTransactionStatus,
+        SimulateOptions, SimulateTransactionResponse, SimulationState, TransactionDetails,
+        TransactionEvents, TransactionEventsXdr,
     },
+    module_cache::ModuleStore,
+    spec::{args_from_json, ContractSpec, SpecIndex},
+    trace::log_message,
     tx_storage::{TransactionInfo, TxStorage},
-    utils::{failed_result, tx_hash},
+    utils::{changes_from_ledger_changes, tx_hash},
     validation::TxValidation,
-    NetworkInfo,
 };
 
+const DEFAULT_LEDGERS_LIMIT: u32 = 5;
+const MAX_LEDGERS_LIMIT: u32 = 200;
+const DEFAULT_TRANSACTIONS_LIMIT: u32 = 10;
+const MAX_TRANSACTIONS_LIMIT: u32 = 200;
+
+/// Simulation threads spawned by the batches running in the process.
+static SIMULATION_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads a batch may spawn, so concurrent batches together stay within the
+/// available parallelism. They are given back on drop.
+struct WorkerPermits(usize);
+
+impl WorkerPermits {
+    /// Takes up to `wanted` threads, besides the calling one, of those left.
+    fn acquire(wanted: usize) -> Self {
+        let limit = thread::available_parallelism().map_or(1, |threads| threads.get());
+        let mut taken = 0;
+        let _ = SIMULATION_WORKERS.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            taken = wanted.min(limit.saturating_sub(used + 1));
+            Some(used + taken)
+        });
+
+        Self(taken)
+    }
+}
+
+impl Drop for WorkerPermits {
+    fn drop(&mut self) {
+        SIMULATION_WORKERS.fetch_sub(self.0, Ordering::AcqRel);
+    }
+}
+
+/// Ledger state at some point, simulations can be run against it.
+#[derive(Clone)]
+struct StateSnapshot {
+    memory: Arc<Memory>,
+    ledger_info: LedgerInfo,
+}
+
 pub struct Sandbox {
-    memory: Rc<Memory>,
+    memory: Arc<Memory>,
+    /// Contract specs by Wasm hash, shared with the simulated states.
+    specs: Arc<SpecIndex>,
+    /// Compiled contract modules, shared with the simulated states.
+    modules: Arc<ModuleStore>,
     ledger_info: LedgerInfo,
     executor: Executor,
     validator: TxValidation,
     tx_storage: TxStorage,
+    ledger_history: LedgerHistory,
+    /// State at the end of every ledger of the history but the latest one.
+    ledger_states: OrdMap<u32, StateSnapshot>,
+    snapshots: Vector<StateSnapshot>,
+    /// Resources used by the last executed transaction or invocation, which
+    /// may run under a shared borrow.
+    last_cost: Mutex<Option<CostRecord>>,
+    costs: CostSnapshot,
+    mock_all_auths: bool,
+    /// Whether contract logs of executed transactions are echoed to stderr.
+    print_logs: bool,
+    impersonated: BTreeSet<ScAddress>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Sandbox {
     pub fn new() -> Self {
-        let memory = Rc::new(Memory::default());
+        let memory = Arc::new(Memory::default());
         let ledger_info = get_initial_ledger_info();
-        let executor = Executor::new(memory.clone());
+        let specs = Arc::new(SpecIndex::default());
+        let modules = Arc::new(ModuleStore::default());
+        let executor = Executor::new(memory.clone(), specs.clone(), modules.clone());
         let validator = TxValidation::new(memory.clone());
 
         Self {
             memory,
+            specs,
+            modules,
+            ledger_history: LedgerHistory::new(&ledger_info),
             ledger_info,
             executor,
             validator,
             tx_storage: TxStorage::default(),
+            ledger_states: OrdMap::new(),
+            snapshots: Vector::new(),
+            last_cost: Mutex::new(None),
+            costs: CostSnapshot::default(),
+            mock_all_auths: false,
+            print_logs: false,
+            impersonated: BTreeSet::new(),
         }
     }
 
@@ -62,15 +156,246 @@ impl Sandbox {
 
     pub fn set_time(&mut self, time: i64) {
         self.ledger_info.timestamp = time as u64;
+        self.close_ledger();
     }
 
+    /// Moves to the given ledger. When moving forward, the state of the
+    /// current ledger is kept for simulations against history. The kept state
+    /// shares the entries with the live one, only entries changed afterwards
+    /// are stored twice.
     pub fn set_sequence(&mut self, seq: u32) {
+        if seq > self.ledger_info.sequence_number {
+            let closed = self.state_snapshot();
+            self.ledger_states
+                .insert(self.ledger_info.sequence_number, closed);
+        }
+
         self.ledger_info.sequence_number = seq;
+        self.close_ledger();
+    }
+
+    /// Sets how many ledgers of history, and transactions in them, are kept.
+    pub fn set_retention_window(&mut self, ledgers: u32) {
+        self.ledger_history.set_retention_window(ledgers);
+        self.prune_history();
+    }
+
+    fn close_ledger(&mut self) {
+        self.ledger_history.close(&self.ledger_info);
+        self.prune_history();
+    }
+
+    fn prune_history(&mut self) {
+        let oldest = self.ledger_history.oldest().sequence;
+        let current = self.ledger_info.sequence_number;
+
+        while let Some((sequence, _)) = self.ledger_states.get_min() {
+            if *sequence >= oldest {
+                break;
+            }
+            self.ledger_states = self.ledger_states.without_min().1;
+        }
+        while let Some((sequence, _)) = self.ledger_states.get_max() {
+            if *sequence < current {
+                break;
+            }
+            self.ledger_states = self.ledger_states.without_max().1;
+        }
+        self.tx_storage.prune(oldest);
+    }
+
+    fn state_snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            memory: Arc::new((*self.memory).clone()),
+            ledger_info: self.ledger_info.clone(),
+        }
+    }
+
+    /// Independent copy of the sandbox, history and settings included. Ledger
+    /// entries, transactions, ledger history and saved states are persistent
+    /// structures shared until either side changes them, so a fork costs
+    /// little whatever the size of the state. Compiled modules and contract
+    /// specs are shared too, they are only kept for code matching its hash.
+    pub fn fork(&self) -> Self {
+        let memory = Arc::new((*self.memory).clone());
+
+        Self {
+            executor: Executor::new(memory.clone(), self.specs.clone(), self.modules.clone()),
+            validator: TxValidation::new(memory.clone()),
+            memory,
+            specs: self.specs.clone(),
+            modules: self.modules.clone(),
+            ledger_info: self.ledger_info.clone(),
+            tx_storage: self.tx_storage.clone(),
+            ledger_history: self.ledger_history.clone(),
+            ledger_states: self.ledger_states.clone(),
+            snapshots: self.snapshots.clone(),
+            last_cost: Mutex::new(self.last_cost()),
+            costs: self.costs.clone(),
+            mock_all_auths: self.mock_all_auths,
+            print_logs: self.print_logs,
+            impersonated: self.impersonated.clone(),
+        }
+    }
+
+    /// Saves the current state, returning the id to simulate against it with.
+    pub fn snapshot(&mut self) -> u32 {
+        self.snapshots.push_back(self.state_snapshot());
+
+        self.snapshots.len() as u32 - 1
+    }
+
+    /// Records the resources used by the last executed transaction or
+    /// invocation under `label`, replacing an earlier record of the same
+    /// label. Rejected transactions leave nothing to record.
+    pub fn record_cost(&mut self, label: &str) -> Result<()> {
+        let cost = self
+            .last_cost()
+            .ok_or_else(|| anyhow!("No transaction was executed to record the cost of"))?;
+        self.costs.record(label, cost);
+
+        Ok(())
+    }
+
+    fn last_cost(&self) -> Option<CostRecord> {
+        *self
+            .last_cost
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set_last_cost(&self, cost: Option<CostRecord>) {
+        *self
+            .last_cost
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = cost;
+    }
+
+    pub fn costs(&self) -> &CostSnapshot {
+        &self.costs
+    }
+
+    /// Compares the recorded costs with a snapshot file, see
+    /// [`CostSnapshot::check`].
+    pub fn compare_cost_snapshot(&self, path: &Path, tolerance: f64) -> Result<()> {
+        self.costs.check(path, tolerance)
     }
 
-    pub fn fund_account(&self, account: String, balance: i64) -> Result<()> {
-        let account_id = AccountId::from_xdr_base64(account, Limits::none())?;
+    pub fn write_cost_snapshot(&self, path: &Path) -> Result<()> {
+        self.costs.write(path)
+    }
+
+    pub fn ledger_history(&self) -> &LedgerHistory {
+        &self.ledger_history
+    }
+
+    pub fn get_latest_ledger(&self) -> GetLatestLedgerResponse {
+        let latest = self.ledger_history.latest();
+
+        GetLatestLedgerResponse {
+            id: hex::encode(latest.hash.0),
+            sequence: latest.sequence,
+            protocol_version: latest.protocol_version,
+        }
+    }
+
+    pub fn get_ledgers(
+        &self,
+        start_ledger: Option<u32>,
+        cursor: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<GetLedgersResponse> {
+        let latest = self.ledger_history.latest();
+        let oldest = self.ledger_history.oldest();
+
+        let start = match (cursor, start_ledger) {
+            (Some(cursor), _) => cursor
+                .parse::<u32>()
+                .ok()
+                .and_then(|ledger| ledger.checked_add(1))
+                .ok_or_else(|| anyhow!("Invalid cursor: {cursor}"))?,
+            (None, Some(start_ledger)) => {
+                ensure!(
+                    (oldest.sequence..=latest.sequence).contains(&start_ledger),
+                    "startLedger must be between the oldest ledger {} and the latest ledger {}",
+                    oldest.sequence,
+                    latest.sequence
+                );
+                start_ledger
+            },
+            (None, None) => bail!("startLedger or cursor must be provided"),
+        };
+        let limit = limit
+            .unwrap_or(DEFAULT_LEDGERS_LIMIT)
+            .min(MAX_LEDGERS_LIMIT);
+
+        let ledgers = self
+            .ledger_history
+            .range(start, limit as usize)
+            .map(|ledger| {
+                Ok(LedgerResponse {
+                    hash: hex::encode(ledger.hash.0),
+                    sequence: ledger.sequence,
+                    ledger_close_time: ledger.close_time.to_string(),
+                    header_xdr: ledger.header_entry().to_xdr_base64(Limits::none())?,
+                    metadata_xdr: ledger.close_meta().to_xdr_base64(Limits::none())?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(GetLedgersResponse {
+            cursor: ledgers
+                .last()
+                .map(|ledger| ledger.sequence)
+                .unwrap_or(start.saturating_sub(1))
+                .to_string(),
+            ledgers,
+            latest_ledger: latest.sequence,
+            latest_ledger_close_time: latest.close_time,
+            oldest_ledger: oldest.sequence,
+            oldest_ledger_close_time: oldest.close_time,
+        })
+    }
+
+    pub fn get_health(&self) -> GetHealthResponse {
+        let latest = self.ledger_history.latest().sequence;
+        let oldest = self.ledger_history.oldest().sequence;
+
+        GetHealthResponse {
+            status: "healthy".to_string(),
+            latest_ledger: latest,
+            oldest_ledger: oldest,
+            ledger_retention_window: self.ledger_history.retention_window(),
+        }
+    }
+
+    pub fn get_version_info(&self) -> GetVersionInfoResponse {
+        GetVersionInfoResponse {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit_hash: String::new(),
+            build_timestamp: String::new(),
+            captive_core_version: "mars-rover sandbox".to_string(),
+            protocol_version: self.ledger_info.protocol_version,
+        }
+    }
+
+    pub fn set_mock_all_auths(&mut self, enabled: bool) {
+        self.mock_all_auths = enabled;
+    }
+
+    pub fn set_print_logs(&mut self, enabled: bool) {
+        self.print_logs = enabled;
+    }
+
+    pub fn impersonate(&mut self, address: ScAddress) {
+        self.impersonated.insert(address);
+    }
+
+    pub fn stop_impersonating(&mut self, address: &ScAddress) {
+        self.impersonated.remove(address);
+    }
 
+    pub fn fund_account(&self, account_id: AccountId, balance: i64) -> Result<()> {
         let signers = vec![].try_into()?;
 
         let account_entry = AccountEntry {
@@ -92,18 +417,245 @@ impl Sandbox {
         Ok(())
     }
 
-    pub fn get_account(&self, account: String) -> Result<String> {
-        let account = self.get_account_from_string(account)?;
+    pub fn set_ledger_entry(&self, entry: LedgerEntry, live_until: Option<u32>) -> Result<()> {
+        self.write_entry(entry, live_until)
+    }
+
+    pub fn set_contract_data(
+        &self,
+        contract: ScAddress,
+        key: ScVal,
+        val: ScVal,
+        durability: ContractDataDurability,
+        live_until: Option<u32>,
+    ) -> Result<()> {
+        let entry = ledger_entry(LedgerEntryData::ContractData(ContractDataEntry {
+            ext: ExtensionPoint::V0,
+            contract,
+            key,
+            durability,
+            val,
+        }));
+
+        self.write_entry(entry, live_until)
+    }
+
+    pub fn delete_ledger_entry(&self, key: LedgerKey) -> Result<()> {
+        let key = Rc::new(key);
+
+        self.memory
+            .get(&key)?
+            .ok_or_else(|| anyhow!("No entry under key: {key:?}"))?;
+        self.memory.remove(&key);
+
+        Ok(())
+    }
+
+    pub fn set_ttl(&self, key: LedgerKey, live_until: u32) -> Result<()> {
+        ensure!(
+            get_key_durability(&key).is_some(),
+            "Entry under key {key:?} has no TTL"
+        );
+
+        let key = Rc::new(key);
+        self.memory
+            .get(&key)?
+            .ok_or_else(|| anyhow!("No entry under key: {key:?}"))?;
+        self.memory.update_ttl(&key, Some(live_until));
 
-        Ok(serde_json::to_string(&account)?)
+        Ok(())
     }
 
-    pub fn get_balance(&self, account: String) -> Result<i64> {
-        self.get_account_from_string(account).map(|x| x.balance)
+    fn write_entry(&self, entry: LedgerEntry, live_until: Option<u32>) -> Result<()> {
+        if let LedgerEntryData::ContractCode(code) = &entry.data {
+            self.specs.add(code);
+        }
+
+        write_entry(&self.memory, &self.ledger_info, entry, live_until)
     }
 
-    fn get_account_from_string(&self, account: String) -> Result<AccountEntry> {
-        let account_id = AccountId::from_xdr_base64(account.clone(), Limits::none())?;
+    pub fn upload_wasm(&self, wasm: Vec<u8>) -> Result<Hash> {
+        let host_fn = HostFunction::UploadContractWasm(wasm.try_into()?);
+
+        match self.run_host_function(&host_fn, &default_source_account())? {
+            ScVal::Bytes(hash) => Ok(Hash(
+                hash.to_vec()
+                    .try_into()
+                    .map_err(|_| anyhow!("wasm hash must be 32 bytes long"))?,
+            )),
+            other => bail!("Unexpected upload result: {other:?}"),
+        }
+    }
+
+    pub fn deploy_contract(
+        &self,
+        wasm_hash: Hash,
+        deployer: ScAddress,
+        salt: Option<[u8; 32]>,
+        constructor_args: Vec<ScVal>,
+    ) -> Result<ScAddress> {
+        let source_account = match &deployer {
+            ScAddress::Account(account_id) => account_id.clone(),
+            _ => default_source_account(),
+        };
+
+        let host_fn = HostFunction::CreateContractV2(CreateContractArgsV2 {
+            contract_id_preimage: ContractIdPreimage::Address(ContractIdPreimageFromAddress {
+                address: deployer,
+                salt: Uint256(salt.unwrap_or_default()),
+            }),
+            executable: ContractExecutable::Wasm(wasm_hash),
+            constructor_args: constructor_args.try_into()?,
+        });
+
+        match self.run_host_function(&host_fn, &source_account)? {
+            ScVal::Address(address) => Ok(address),
+            other => bail!("Unexpected deploy result: {other:?}"),
+        }
+    }
+
+    /// Spec of the Wasm a contract runs, `None` for Stellar asset contracts
+    /// and Wasm without a spec section.
+    pub fn contract_spec(&self, contract: &ScAddress) -> Option<Arc<ContractSpec>> {
+        match contract {
+            ScAddress::Contract(id) => self.specs.contract_spec(&self.memory, id),
+            _ => None,
+        }
+    }
+
+    /// Arguments of a contract function given as JSON, converted per the
+    /// contract spec when there is one.
+    pub fn args_from_json(
+        &self,
+        contract: &ScAddress,
+        fn_name: &str,
+        args: &serde_json::Value,
+    ) -> Result<Vec<ScVal>> {
+        args_from_json(self.contract_spec(contract).as_deref(), fn_name, args)
+    }
+
+    pub fn invoke(
+        &self,
+        contract_address: ScAddress,
+        fn_name: &str,
+        args: Vec<ScVal>,
+        source_account: Option<AccountId>,
+        auth_mode: AuthMode,
+        commit: bool,
+    ) -> Result<InvokeResponse> {
+        let source_account = source_account.unwrap_or_else(default_source_account);
+        let spec = self.contract_spec(&contract_address);
+
+        let host_fn = HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address,
+            function_name: fn_name
+                .try_into()
+                .map_err(|_| anyhow!("Invalid function name"))?,
+            args: args.try_into()?,
+        });
+
+        let execution = self.executor.invoke_in_recording_mode(
+            &host_fn,
+            &source_account,
+            auth_mode.into(),
+            &self.ledger_info,
+        )?;
+        self.echo_logs(&execution.diagnostic_events);
+        let changes = match &execution.result.invoke_result {
+            Ok(_) => &execution.result.ledger_changes[..],
+            Err(_) => &[],
+        };
+        let profile = self.executor.profile(&execution.budget, changes)?;
+        self.set_last_cost(Some(CostRecord {
+            instructions: profile.cpu_insns,
+            read_bytes: profile.read_bytes,
+            write_bytes: profile.write_bytes,
+            fee: self
+                .executor
+                .recorded_fee(&host_fn, &execution, &self.ledger_info)?,
+        }));
+        let result = execution.result;
+
+        let retval = match result.invoke_result {
+            Ok(retval) => retval,
+            Err(e) => {
+                let error = spec
+                    .and_then(|spec| spec.describe_error(e.error))
+                    .unwrap_or_else(|| e.to_string());
+
+                return Ok(InvokeResponse {
+                    retval: None,
+                    error: Some(error),
+                    events: vec![],
+                    diagnostic_events: execution.diagnostic_events,
+                    auth: vec![],
+                    cpu_insns: execution.cpu_insns,
+                    mem_bytes: execution.mem_bytes,
+                    profile,
+                    state_changes: vec![],
+                });
+            },
+        };
+
+        let state_changes = changes_from_ledger_changes(&self.memory, &result.ledger_changes)?;
+
+        if commit {
+            self.executor
+                .apply_ledger_changes(result.ledger_changes, &self.ledger_info)?;
+        }
+
+        Ok(InvokeResponse {
+            retval: Some(retval.to_xdr_base64(Limits::none())?),
+            error: None,
+            events: result
+                .contract_events
+                .iter()
+                .map(|event| event.to_xdr_base64(Limits::none()))
+                .collect::<Result<Vec<_>, _>>()?,
+            diagnostic_events: execution.diagnostic_events,
+            auth: result
+                .auth
+                .iter()
+                .map(|auth| auth.to_xdr_base64(Limits::none()))
+                .collect::<Result<Vec<_>, _>>()?,
+            cpu_insns: execution.cpu_insns,
+            mem_bytes: execution.mem_bytes,
+            profile,
+            state_changes,
+        })
+    }
+
+    /// Runs the host function with recorded footprint and all authorizations
+    /// mocked, then writes its changes straight into memory.
+    fn run_host_function(
+        &self,
+        host_fn: &HostFunction,
+        source_account: &AccountId,
+    ) -> Result<ScVal> {
+        let result = self
+            .executor
+            .invoke_in_recording_mode(
+                host_fn,
+                source_account,
+                RecordingInvocationAuthMode::Recording(false),
+                &self.ledger_info,
+            )?
+            .result;
+
+        let value = result
+            .invoke_result
+            .map_err(|e| anyhow!("host function failed: {e:?}"))?;
+        self.executor
+            .apply_ledger_changes(result.ledger_changes, &self.ledger_info)?;
+
+        Ok(value)
+    }
+
+    pub fn get_balance(&self, account_id: AccountId) -> Result<i64> {
+        self.get_account(account_id).map(|x| x.balance)
+    }
+
+    pub fn get_account(&self, account_id: AccountId) -> Result<AccountEntry> {
         let key = LedgerKey::from(LedgerKeyAccount { account_id });
 
         self.memory
@@ -112,22 +664,136 @@ impl Sandbox {
             .ok_or_else(|| anyhow!("account not found"))
     }
 
-    pub fn simulate_tx(&self, transaction_envelope: String) -> Result<String> {
-        let te = TransactionEnvelope::from_xdr_base64(&transaction_envelope, Limits::none())?;
-
-        let envelope = match te {
+    pub fn simulate_transaction(
+        &self,
+        transaction_envelope: TransactionEnvelope,
+        options: SimulateOptions,
+    ) -> Result<SimulateTransactionResponse> {
+        let envelope = match transaction_envelope {
             TransactionEnvelope::Tx(envelope) => envelope,
             _ => bail!("Unsupported transaction type"),
         };
+        let (memory, ledger_info) = self.simulation_state(&options)?;
 
-        let response = self
-            .executor
-            .simulate_transaction(envelope, &self.ledger_info)?;
+        Executor::new(memory, self.specs.clone(), self.modules.clone()).simulate_transaction(
+            envelope,
+            ledger_info,
+            options,
+        )
+    }
+
+    /// Simulates the transactions concurrently against the same state, on as
+    /// many threads as the machine runs in parallel. Responses are in the
+    /// order of the envelopes.
+    pub fn simulate_batch(
+        &self,
+        transaction_envelopes: Vec<TransactionEnvelope>,
+        options: SimulateOptions,
+    ) -> Result<Vec<SimulateTransactionResponse>> {
+        let envelopes = transaction_envelopes
+            .into_iter()
+            .enumerate()
+            .map(|(index, envelope)| match envelope {
+                TransactionEnvelope::Tx(envelope) => Ok(envelope),
+                _ => bail!("Unsupported type of transaction {index}"),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (memory, ledger_info) = self.simulation_state(&options)?;
+        let executor = Executor::new(memory, self.specs.clone(), self.modules.clone());
 
-        Ok(serde_json::to_string(&response)?)
+        let workers = WorkerPermits::acquire(envelopes.len().saturating_sub(1));
+        let next = AtomicUsize::new(0);
+        let mut responses: Vec<_> = envelopes.iter().map(|_| None).collect();
+
+        let simulate = || {
+            let mut simulated = vec![];
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(envelope) = envelopes.get(index) else {
+                    return simulated;
+                };
+                let response =
+                    executor.simulate_transaction(envelope.clone(), ledger_info, options.clone());
+                simulated.push((index, response));
+            }
+        };
+
+        thread::scope(|scope| {
+            let spawned: Vec<_> = (0..workers.0).map(|_| scope.spawn(simulate)).collect();
+
+            // The calling thread takes part, so a batch makes progress even
+            // when other batches hold every worker.
+            let mut simulated = simulate();
+            for worker in spawned {
+                simulated.extend(
+                    worker
+                        .join()
+                        .map_err(|_| anyhow!("Simulation thread panicked"))?,
+                );
+            }
+            for (index, response) in simulated {
+                responses[index] = Some(response);
+            }
+
+            Ok::<_, anyhow::Error>(())
+        })?;
+        drop(workers);
+
+        responses
+            .into_iter()
+            .enumerate()
+            .map(|(index, response)| {
+                response
+                    .ok_or_else(|| anyhow!("Transaction {index} was not simulated"))?
+                    .map_err(|e| anyhow!("Failed to simulate transaction {index}: {e}"))
+            })
+            .collect()
     }
 
-    fn apply_account_changes(&self, account_id: AccountId) -> Result<()> {
+    /// State a simulation runs against, a copy when entries are overridden.
+    fn simulation_state(&self, options: &SimulateOptions) -> Result<(Arc<Memory>, &LedgerInfo)> {
+        let (memory, ledger_info) = match options.state {
+            SimulationState::Latest => (&self.memory, &self.ledger_info),
+            SimulationState::Ledger(sequence) if sequence == self.ledger_info.sequence_number => {
+                (&self.memory, &self.ledger_info)
+            },
+            SimulationState::Ledger(sequence) => {
+                let state = self
+                    .ledger_states
+                    .get(&sequence)
+                    .ok_or_else(|| anyhow!("Ledger {sequence} is not in the history"))?;
+                (&state.memory, &state.ledger_info)
+            },
+            SimulationState::Snapshot(id) => {
+                let state = self
+                    .snapshots
+                    .get(id as usize)
+                    .ok_or_else(|| anyhow!("Snapshot {id} not found"))?;
+                (&state.memory, &state.ledger_info)
+            },
+        };
+
+        // Overrides go to a copy, so they are gone after the simulation.
+        // Simulations don't use the module store, overridden code runs as
+        // written even under the hash of compiled code.
+        let memory = match &options.overrides {
+            Some(overrides) => {
+                let memory = Arc::new((**memory).clone());
+                for key in &overrides.removed {
+                    memory.remove(key);
+                }
+                for (entry, live_until) in &overrides.entries {
+                    write_entry(&memory, ledger_info, entry.clone(), *live_until)?;
+                }
+                memory
+            },
+            None => memory.clone(),
+        };
+
+        Ok((memory, ledger_info))
+    }
+
+    fn apply_account_changes(&self, account_id: AccountId, seq_num: i64) -> Result<()> {
         let key = Rc::new(LedgerKey::from(LedgerKeyAccount { account_id }));
 
         let (entry, ttl) = self.memory.get(&key)?.ok_or(anyhow!("No entry"))?;
@@ -137,7 +803,7 @@ impl Sandbox {
             _ => bail!("account not found"),
         };
 
-        account.seq_num = SequenceNumber(account.seq_num.0 + 1);
+        account.seq_num = SequenceNumber(seq_num);
 
         let entry = LedgerEntry {
             data: LedgerEntryData::Account(account),
@@ -150,37 +816,101 @@ impl Sandbox {
         Ok(())
     }
 
-    pub fn get_network_info(&self) -> napi::Result<String> {
-        let network_info = NetworkInfo {
+    pub fn get_network_info(&self) -> NetworkInfo {
+        NetworkInfo {
             passphrase: NETWORK_PASSPHRASE.to_string(),
             protocol_version: self.ledger_info.protocol_version.to_string(),
-        };
-
-        serde_json::to_string(&network_info)
-            .map_err(|e| Error::from_reason(format!("network info serialization failed: {}", e)))
+        }
     }
 
     pub fn send_transaction(
         &mut self,
-        transaction_envelope: String,
+        transaction_envelope: TransactionEnvelope,
+        mock_all_auths: Option<bool>,
     ) -> Result<SendTransactionResponse> {
-        let te = TransactionEnvelope::from_xdr_base64(&transaction_envelope, Limits::none())
-            .map_err(|e| Error::from_reason(format!("invalid transaction envelope: {}", e)))?;
-
-        let envelope = match te {
+        self.set_last_cost(None);
+        let envelope = match transaction_envelope {
             TransactionEnvelope::Tx(envelope) => envelope,
             _ => bail!("Unsupported transaction type"),
         };
 
-        let result = self.send_transaction_inner(&envelope);
+        let hash = tx_hash(&envelope, &self.ledger_info)?;
+        let hash = hex::encode(hash);
+
+        if self.tx_storage.get(&hash).is_some() {
+            return Ok(SendTransactionResponse {
+                base: self.base_send_response(hash, SendTransactionStatus::Duplicate),
+                error_result: None,
+                diagnostic_events: None,
+                required_auth: None,
+                footprint_diagnostics: None,
+                trace: None,
+                profile: None,
+            });
+        }
+
+        // Invalid transactions are rejected before being applied, so they do
+        // not consume a sequence number.
+        let source = ScAddress::Account(envelope.tx.source_account.clone().account_id());
+        let validated = self.validator.validate_soroban(&envelope).and_then(|()| {
+            self.validator.validate(
+                &envelope,
+                &self.ledger_info,
+                !self.impersonated.contains(&source),
+            )
+        });
+        if let Err(invalid) = validated {
+            return Ok(SendTransactionResponse {
+                base: self.base_send_response(hash, SendTransactionStatus::Error),
+                error_result: Some(TransactionResult {
+                    fee_charged: 0,
+                    result: invalid.result.clone(),
+                    ext: Default::default(),
+                }),
+                diagnostic_events: Some(vec![invalid.diagnostic_event()?]),
+                required_auth: None,
+                footprint_diagnostics: None,
+                trace: None,
+                profile: None,
+            });
+        }
 
         let account_id = envelope.tx.source_account.clone().account_id();
-        self.apply_account_changes(account_id)?;
 
-        let hash = tx_hash(&envelope, &self.ledger_info)?;
-        let hash = hex::encode(hash);
+        if let [Operation {
+            body: OperationBody::BumpSequence(op),
+            ..
+        }] = envelope.tx.operations.as_slice()
+        {
+            let seq_num = op.bump_to.0.max(envelope.tx.seq_num.0);
+            self.apply_account_changes(account_id, seq_num)?;
+            self.tx_storage.insert(
+                hash.clone(),
+                TransactionInfo {
+                    envelope,
+                    result: Ok(None),
+                    events: vec![],
+                    diagnostic_events: vec![],
+                    ledger_info: self.ledger_info.clone(),
+                    application_order: 0,
+                },
+            );
+
+            return Ok(SendTransactionResponse {
+                base: self.base_send_response(hash, SendTransactionStatus::Pending),
+                error_result: None,
+                diagnostic_events: None,
+                required_auth: None,
+                footprint_diagnostics: None,
+                trace: None,
+                profile: None,
+            });
+        }
 
-        let result = match result {
+        self.apply_account_changes(account_id, envelope.tx.seq_num.0)?;
+
+        let mock_all_auths = mock_all_auths.unwrap_or(self.mock_all_auths);
+        let result = match self.send_transaction_inner(&envelope, mock_all_auths) {
             Ok(result) => result,
             Err(e) => {
                 self.tx_storage.insert(
@@ -189,7 +919,9 @@ impl Sandbox {
                         envelope,
                         result: Err(e.to_string()),
                         events: vec![],
+                        diagnostic_events: vec![],
                         ledger_info: self.ledger_info.clone(),
+                        application_order: 0,
                     },
                 );
 
@@ -197,48 +929,97 @@ impl Sandbox {
             },
         };
 
+        self.set_last_cost(Some(CostRecord {
+            instructions: result.profile.cpu_insns,
+            read_bytes: result.profile.read_bytes,
+            write_bytes: result.profile.write_bytes,
+            fee: result.resource_fee,
+        }));
+        self.echo_logs(&result.diagnostic_events);
+
         let status = match &result.result {
             Ok(_) => SendTransactionStatus::Pending,
             _ => SendTransactionStatus::Error,
         };
 
         let response = SendTransactionResponse {
-            base: BaseSendTransactionResponse {
-                status,
-                hash: hash.clone(),
-                latest_ledger: self.ledger_info.sequence_number,
-                latest_ledger_close_time: self.ledger_info.timestamp,
-            },
+            base: self.base_send_response(hash.clone(), status),
             error_result: result.error.clone().map(|error| TransactionResult {
                 fee_charged: result.fee_charges,
                 result: error,
                 ext: Default::default(),
             }),
-            diagnostic_events: result.error.is_some().then_some(result.events.clone()),
+            diagnostic_events: result
+                .error
+                .is_some()
+                .then(|| result.diagnostic_events.clone()),
+            required_auth: result
+                .required_auth
+                .as_ref()
+                .map(|auth| {
+                    auth.iter()
+                        .map(|entry| entry.to_xdr_base64(Limits::none()))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?,
+            footprint_diagnostics: result.footprint_diagnostics,
+            trace: Some(result.trace),
+            profile: Some(result.profile),
         };
 
         self.tx_storage.insert(
             hash,
             TransactionInfo {
                 envelope,
-                result: result.result.map_err(|e| e.to_string()),
+                result: result.result.map(Some).map_err(|e| e.to_string()),
                 events: result.events,
+                diagnostic_events: result.diagnostic_events,
                 ledger_info: self.ledger_info.clone(),
+                application_order: 0,
             },
         );
 
         Ok(response)
     }
 
+    fn echo_logs(&self, events: &[DiagnosticEvent]) {
+        if self.print_logs {
+            for line in events.iter().filter_map(log_message) {
+                eprintln!("{line}");
+            }
+        }
+    }
+
+    fn base_send_response(
+        &self,
+        hash: String,
+        status: SendTransactionStatus,
+    ) -> BaseSendTransactionResponse {
+        BaseSendTransactionResponse {
+            status,
+            hash,
+            latest_ledger: self.ledger_info.sequence_number,
+            latest_ledger_close_time: self.ledger_info.timestamp,
+        }
+    }
+
+    /// Executes an already validated transaction.
     pub fn send_transaction_inner(
         &self,
         envelope: &TransactionV1Envelope,
+        mock_all_auths: bool,
     ) -> Result<ExecutionResult> {
-        self.validator.validate(envelope, &self.ledger_info)?;
+        let auth_override = if mock_all_auths {
+            AuthOverride::MockAll
+        } else if !self.impersonated.is_empty() {
+            AuthOverride::Impersonate(&self.impersonated)
+        } else {
+            AuthOverride::None
+        };
 
         let result = self
             .executor
-            .send_transaction(envelope, &self.ledger_info)
+            .send_transaction(envelope, &self.ledger_info, auth_override)
             .map_err(|e| anyhow!("transaction execution failed: {:?}", e))?;
 
         Ok(result)
@@ -246,20 +1027,10 @@ impl Sandbox {
 
     pub fn get_contract_data(
         &self,
-        contract_address: String,
-        key: String,
-        durability: String,
+        contract: ScAddress,
+        key: ScVal,
+        durability: ContractDataDurability,
     ) -> Result<LedgerEntryResult> {
-        let contract = ScAddress::from_xdr_base64(contract_address, Limits::none())
-            .context("Invalid contract address XDR")?;
-        let key = ScVal::from_xdr_base64(key, Limits::none()).context("Invalid key XDR")?;
-
-        let durability = match durability.as_str() {
-            "persistent" => ContractDataDurability::Persistent,
-            "temporary" => ContractDataDurability::Temporary,
-            _ => bail!("Invalid durability: {}", durability),
-        };
-
         let key = LedgerKey::from(LedgerKeyContractData {
             contract: contract.clone(),
             key,
@@ -278,6 +1049,28 @@ impl Sandbox {
         })
     }
 
+    pub fn get_ledger_entries(&self, keys: Vec<LedgerKey>) -> Result<Vec<LedgerEntryResult>> {
+        let mut entries = Vec::new();
+
+        for key in keys {
+            if let Some((entry, ttl)) = self.memory.get(&Rc::new(key.clone()))? {
+                entries.push(LedgerEntryResult {
+                    last_modified_ledger_seq: Some(entry.last_modified_ledger_seq),
+                    key: key.to_xdr_base64(Limits::none())?,
+                    val: entry.data.to_xdr_base64(Limits::none())?,
+                    live_until_ledger_seq: ttl,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Sent transactions in the order they were submitted.
+    pub fn transactions(&self) -> impl Iterator<Item = (&str, &TransactionInfo)> {
+        self.tx_storage.iter()
+    }
+
     pub fn get_transaction(&self, hash: String) -> Result<GetTransactionResponse> {
         let ti = match self.tx_storage.get(&hash) {
             Some(ti) => ti,
@@ -287,8 +1080,8 @@ impl Sandbox {
                         tx_hash: hash,
                         latest_ledger: self.ledger_info.sequence_number,
                         latest_ledger_close_time: self.ledger_info.timestamp,
-                        oldest_ledger: 0,
-                        oldest_ledger_close_time: 0,
+                        oldest_ledger: self.ledger_history.oldest().sequence,
+                        oldest_ledger_close_time: self.ledger_history.oldest().close_time,
                     },
                 ))
             },
@@ -300,34 +1093,17 @@ impl Sandbox {
                     tx_hash: hash.clone(),
                     latest_ledger: self.ledger_info.sequence_number,
                     latest_ledger_close_time: self.ledger_info.timestamp,
-                    oldest_ledger: 0,
-                    oldest_ledger_close_time: 0,
+                    oldest_ledger: self.ledger_history.oldest().sequence,
+                    oldest_ledger_close_time: self.ledger_history.oldest().close_time,
                     ledger: ti.ledger_info.sequence_number,
                     created_at: ti.ledger_info.timestamp,
-                    application_order: 0,
+                    application_order: ti.application_order,
                     fee_bump: false,
-                    envelope_xdr: TransactionEnvelope::Tx(ti.envelope.clone())
-                        .to_xdr_base64(Limits::none())?,
-                    result_xdr: TransactionResult {
-                        fee_charged: ti.envelope.tx.fee as i64,
-                        result: TransactionResultResult::TxSuccess(
-                            vec![OperationResult::OpInner(
-                                OperationResultTr::InvokeHostFunction(
-                                    InvokeHostFunctionResult::Success(Hash(
-                                        hex::decode(hash)?
-                                            .try_into()
-                                            .map_err(|e| anyhow!("coudl not decode {e:?}"))?,
-                                    )),
-                                ),
-                            )]
-                            .try_into()?,
-                        ),
-                        ext: Default::default(),
-                    }
-                    .to_xdr_base64(Limits::none())?,
-                    result_meta_xdr: Default::default(),
-                    diagnostic_events_xdr: None,
-                    return_value: Some(result.clone()),
+                    envelope_xdr: ti.envelope_xdr()?,
+                    result_xdr: ti.result_xdr(&hash)?,
+                    result_meta_xdr: ti.result_meta_xdr()?,
+                    diagnostic_events_xdr: ti.diagnostic_events_xdr()?,
+                    return_value: result.clone(),
                     events: TransactionEvents {
                         transaction_events_xdr: vec![],
                         contract_events_xdr: vec![ti
@@ -343,22 +1119,16 @@ impl Sandbox {
                     tx_hash: hash.clone(),
                     latest_ledger: self.ledger_info.sequence_number,
                     latest_ledger_close_time: self.ledger_info.timestamp,
-                    oldest_ledger: 0,
-                    oldest_ledger_close_time: 0,
+                    oldest_ledger: self.ledger_history.oldest().sequence,
+                    oldest_ledger_close_time: self.ledger_history.oldest().close_time,
                     ledger: ti.ledger_info.sequence_number,
                     created_at: ti.ledger_info.timestamp,
-                    application_order: 0,
+                    application_order: ti.application_order,
                     fee_bump: false,
-                    envelope_xdr: TransactionEnvelope::Tx(ti.envelope.clone())
-                        .to_xdr_base64(Limits::none())?,
-                    result_xdr: TransactionResult {
-                        fee_charged: ti.envelope.tx.fee as i64,
-                        result: failed_result()?,
-                        ext: Default::default(),
-                    }
-                    .to_xdr_base64(Limits::none())?,
-                    result_meta_xdr: Default::default(),
-                    diagnostic_events_xdr: None,
+                    envelope_xdr: ti.envelope_xdr()?,
+                    result_xdr: ti.result_xdr(&hash)?,
+                    result_meta_xdr: ti.result_meta_xdr()?,
+                    diagnostic_events_xdr: ti.diagnostic_events_xdr()?,
                     events: TransactionEvents {
                         transaction_events_xdr: vec![],
                         contract_events_xdr: vec![ti
@@ -371,4 +1141,588 @@ impl Sandbox {
             )),
         }
     }
+
+    pub fn get_transactions(
+        &self,
+        start_ledger: Option<u32>,
+        cursor: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<GetTransactionsResponse> {
+        let latest = self.ledger_history.latest();
+        let oldest = self.ledger_history.oldest();
+
+        let (ledger, application_order) = match (cursor, start_ledger) {
+            (Some(cursor), _) => {
+                let toid = cursor
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid cursor: {cursor}"))?;
+                ((toid >> 32) as u32, ((toid >> 12) & 0xFFFFF) as u32 + 1)
+            },
+            (None, Some(start_ledger)) => {
+                ensure!(
+                    (oldest.sequence..=latest.sequence).contains(&start_ledger),
+                    "startLedger must be between the oldest ledger {} and the latest ledger {}",
+                    oldest.sequence,
+                    latest.sequence
+                );
+                (start_ledger, 0)
+            },
+            (None, None) => bail!("startLedger or cursor must be provided"),
+        };
+        let limit = limit
+            .unwrap_or(DEFAULT_TRANSACTIONS_LIMIT)
+            .min(MAX_TRANSACTIONS_LIMIT);
+
+        let transactions = self
+            .tx_storage
+            .iter_from(ledger, application_order)
+            .take(limit as usize)
+            .map(|(hash, ti)| {
+                Ok(TransactionDetails {
+                    status: match ti.result {
+                        Ok(_) => GetTransactionStatus::Success,
+                        Err(_) => GetTransactionStatus::Failed,
+                    },
+                    tx_hash: hash.to_string(),
+                    application_order: ti.application_order,
+                    fee_bump: false,
+                    envelope_xdr: ti.envelope_xdr()?,
+                    result_xdr: ti.result_xdr(hash)?,
+                    result_meta_xdr: ti.result_meta_xdr()?,
+                    diagnostic_events_xdr: ti.diagnostic_events_xdr()?,
+                    ledger: ti.ledger_info.sequence_number,
+                    created_at: ti.ledger_info.timestamp,
+                    events: TransactionEventsXdr {
+                        transaction_events_xdr: vec![],
+                        contract_events_xdr: vec![ti
+                            .events
+                            .iter()
+                            .map(|e| e.event.to_xdr_base64(Limits::none()))
+                            .collect::<Result<_, _>>()?],
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let cursor = match transactions.last() {
+            Some(last) => toid(last.ledger, last.application_order),
+            None => toid(ledger, application_order.saturating_sub(1)),
+        };
+
+        Ok(GetTransactionsResponse {
+            transactions,
+            latest_ledger: latest.sequence,
+            latest_ledger_close_timestamp: latest.close_time,
+            oldest_ledger: oldest.sequence,
+            oldest_ledger_close_timestamp: oldest.close_time,
+            cursor: cursor.to_string(),
+        })
+    }
+}
+
+/// Writes the entry bypassing execution. Contract data and code entries
+/// without an explicit `live_until` get the minimal TTL for their durability,
+/// just like freshly created entries.
+fn write_entry(
+    memory: &Memory,
+    ledger_info: &LedgerInfo,
+    mut entry: LedgerEntry,
+    live_until: Option<u32>,
+) -> Result<()> {
+    let ttl = match get_key_durability(&entry.to_key()) {
+        Some(durability) => Some(match live_until {
+            Some(live_until) => live_until,
+            None => ledger_info
+                .min_live_until_ledger_checked(durability)
+                .ok_or_else(|| anyhow!("live until ledger overflow"))?,
+        }),
+        None => {
+            ensure!(
+                live_until.is_none(),
+                "Only contract data and code entries have TTL"
+            );
+            None
+        },
+    };
+
+    entry.last_modified_ledger_seq = ledger_info.sequence_number;
+    memory.insert_with_ttl(entry, ttl);
+
+    Ok(())
+}
+
+/// Total order id of a transaction, as used by stellar-rpc cursors.
+pub fn toid(ledger: u32, application_order: u32) -> u64 {
+    ((ledger as u64) << 32) | ((application_order as u64) << 12)
+}
+
+fn default_source_account() -> AccountId {
+    AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([0; 32])))
+}
+
+#[cfg(test)]
+mod tests {
+    use soroban_env_host::{
+        budget::Budget,
+        e2e_invoke,
+        xdr::{
+            ContractCodeEntry, ContractCodeEntryExt, InvokeHostFunctionOp, Preconditions, ReadXdr,
+            TimeBounds, TimePoint, TransactionExt, TransactionResultResult,
+        },
+    };
+    use soroban_simulation::simulation::{
+        simulate_invoke_host_function_op, SimulationAdjustmentConfig,
+    };
+
+    use super::*;
+    use crate::{
+        model::StateOverrides,
+        network_config::default_network_config,
+        test_utils::{answer_contract, bump_sequence, deploy_adapter, simulated, transaction, Key},
+        trace::replay_frame_costs,
+    };
+
+    fn send(sandbox: &mut Sandbox, envelope: TransactionV1Envelope) -> SendTransactionResponse {
+        sandbox
+            .send_transaction(TransactionEnvelope::Tx(envelope), None)
+            .unwrap()
+    }
+
+    /// Result of a transaction rejected before being applied.
+    fn rejection(
+        sandbox: &mut Sandbox,
+        envelope: TransactionV1Envelope,
+    ) -> TransactionResultResult {
+        let response = send(sandbox, envelope);
+        assert_eq!(response.base.status, SendTransactionStatus::Error);
+
+        response.error_result.unwrap().result
+    }
+
+    fn funded(balance: i64) -> (Sandbox, Key) {
+        let sandbox = Sandbox::new();
+        let key = Key::new(1);
+        sandbox.fund_account(key.account(), balance).unwrap();
+
+        (sandbox, key)
+    }
+
+    #[test]
+    fn accepts_valid_transactions() {
+        let (mut sandbox, key) = funded(1_000_000_000);
+        let mut envelope = bump_sequence(&sandbox, &key);
+        key.sign(&sandbox, &mut envelope);
+
+        let response = send(&mut sandbox, envelope);
+        assert_eq!(response.base.status, SendTransactionStatus::Pending);
+        assert_eq!(sandbox.get_account(key.account()).unwrap().seq_num.0, 1);
+    }
+
+    #[test]
+    fn rejects_missing_accounts() {
+        let (sandbox, key) = funded(1_000_000_000);
+        let mut envelope = bump_sequence(&sandbox, &key);
+        key.sign(&sandbox, &mut envelope);
+
+        let result = rejection(&mut Sandbox::new(), envelope);
+        assert_eq!(result, TransactionResultResult::TxNoAccount);
+    }
+
+    #[test]
+    fn rejects_bad_sequence_numbers() {
+        let (mut sandbox, key) = funded(1_000_000_000);
+        let mut envelope = bump_sequence(&sandbox, &key);
+        envelope.tx.seq_num.0 += 1;
+        key.sign(&sandbox, &mut envelope);
+
+        let result = rejection(&mut sandbox, envelope);
+        assert_eq!(result, TransactionResultResult::TxBadSeq);
+        assert_eq!(sandbox.get_account(key.account()).unwrap().seq_num.0, 0);
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let (mut sandbox, key) = funded(1_000_000_000);
+
+        let unsigned = bump_sequence(&sandbox, &key);
+        let result = rejection(&mut sandbox, unsigned);
+        assert_eq!(result, TransactionResultResult::TxBadAuth);
+
+        let mut signed_by_other = bump_sequence(&sandbox, &key);
+        Key::new(2).sign(&sandbox, &mut signed_by_other);
+        let result = rejection(&mut sandbox, signed_by_other);
+        assert_eq!(result, TransactionResultResult::TxBadAuth);
+
+        assert_eq!(sandbox.get_account(key.account()).unwrap().seq_num.0, 0);
+    }
+
+    #[test]
+    fn rejects_insufficient_balances() {
+        let (mut sandbox, key) = funded(100);
+        let mut envelope = bump_sequence(&sandbox, &key);
+        key.sign(&sandbox, &mut envelope);
+
+        let result = rejection(&mut sandbox, envelope);
+        assert_eq!(result, TransactionResultResult::TxInsufficientBalance);
+    }
+
+    #[test]
+    fn rejects_transactions_out_of_time_bounds() {
+        let (mut sandbox, key) = funded(1_000_000_000);
+        sandbox.set_time(1_000);
+
+        let bounded = |sandbox: &Sandbox, min_time, max_time| {
+            let mut envelope = bump_sequence(sandbox, &key);
+            envelope.tx.cond = Preconditions::Time(TimeBounds {
+                min_time: TimePoint(min_time),
+                max_time: TimePoint(max_time),
+            });
+            key.sign(sandbox, &mut envelope);
+            envelope
+        };
+
+        let too_early = bounded(&sandbox, 1_001, 0);
+        let result = rejection(&mut sandbox, too_early);
+        assert_eq!(result, TransactionResultResult::TxTooEarly);
+
+        let too_late = bounded(&sandbox, 0, 999);
+        let result = rejection(&mut sandbox, too_late);
+        assert_eq!(result, TransactionResultResult::TxTooLate);
+
+        let within = bounded(&sandbox, 1_000, 0);
+        let response = send(&mut sandbox, within);
+        assert_eq!(response.base.status, SendTransactionStatus::Pending);
+    }
+
+    #[test]
+    fn invocations_cost_the_same_as_upstream() {
+        let (sandbox, key) = funded(1_000_000_000);
+        let contract = deploy_adapter(&sandbox, &key);
+        let args = vec![ScVal::Address(ScAddress::Account(key.account()))];
+        let response = sandbox
+            .invoke(
+                contract.clone(),
+                "init",
+                args.clone(),
+                None,
+                AuthMode::MockAll,
+                false,
+            )
+            .unwrap();
+        assert_eq!(response.error, None);
+
+        let host_fn = HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: contract,
+            function_name: "init".try_into().unwrap(),
+            args: args.try_into().unwrap(),
+        });
+        let budget = Budget::default();
+        budget.reset_unlimited().unwrap();
+        let upstream = e2e_invoke::invoke_host_function_in_recording_mode(
+            &budget,
+            true,
+            &host_fn,
+            &default_source_account(),
+            RecordingInvocationAuthMode::Recording(false),
+            sandbox.ledger_info.clone(),
+            sandbox.memory.snapshot_source(),
+            [0; 32],
+            &mut vec![],
+        )
+        .unwrap();
+        assert!(upstream.invoke_result.is_ok());
+        assert_eq!(response.cpu_insns, budget.get_cpu_insns_consumed().unwrap());
+        assert_eq!(response.mem_bytes, budget.get_mem_bytes_consumed().unwrap());
+        assert_eq!(response.profile.cpu_insns, response.cpu_insns);
+
+        // The replayed call is charged everything but the work around it.
+        let frame_costs = replay_frame_costs(
+            &host_fn,
+            &default_source_account(),
+            &RecordingInvocationAuthMode::Recording(false),
+            &sandbox.ledger_info,
+            sandbox.memory.snapshot_source(),
+            [0; 32],
+        )
+        .unwrap();
+        assert_eq!(frame_costs.len(), 1);
+        assert!(frame_costs[0].cpu_insns > 0);
+        assert!(frame_costs[0].cpu_insns < response.cpu_insns);
+    }
+
+    #[test]
+    fn simulations_match_soroban_simulation() {
+        let (sandbox, key) = funded(1_000_000_000);
+        let contract = deploy_adapter(&sandbox, &key);
+        let host_fn = HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: contract,
+            function_name: "init".try_into().unwrap(),
+            args: vec![ScVal::Address(ScAddress::Account(key.account()))]
+                .try_into()
+                .unwrap(),
+        });
+        let envelope = transaction(
+            &sandbox,
+            &key,
+            OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                host_function: host_fn.clone(),
+                auth: Default::default(),
+            }),
+        );
+        let SimulateTransactionResponse::Success(simulated) = sandbox
+            .simulate_transaction(
+                TransactionEnvelope::Tx(envelope),
+                SimulateOptions::default(),
+            )
+            .unwrap()
+        else {
+            panic!("simulation failed");
+        };
+
+        let expected = simulate_invoke_host_function_op(
+            sandbox.memory.snapshot_source(),
+            &default_network_config().unwrap(),
+            &SimulationAdjustmentConfig::default_adjustment(),
+            &sandbox.ledger_info,
+            host_fn,
+            RecordingInvocationAuthMode::Recording(true),
+            &key.account(),
+            [1; 32],
+            true,
+        )
+        .unwrap();
+        let transaction_data = expected.transaction_data.unwrap();
+        assert_eq!(
+            simulated.transaction_data,
+            transaction_data.to_xdr_base64(Limits::none()).unwrap()
+        );
+        assert_eq!(
+            simulated.min_resource_fee,
+            transaction_data.resource_fee.to_string()
+        );
+        assert_eq!(
+            simulated.cost.cpu_insns,
+            expected.simulated_instructions.to_string()
+        );
+
+        let [call] = &simulated.trace.calls[..] else {
+            panic!("expected a single call");
+        };
+        assert!(call.cpu_insns.is_some_and(
+            |cpu_insns| cpu_insns > 0 && cpu_insns < u64::from(expected.simulated_instructions)
+        ));
+    }
+
+    /// Adapter owned by `owner`, with `user` funded to send transactions.
+    fn owned_adapter(owner: &Key, user: &Key) -> (Sandbox, ScAddress) {
+        let (sandbox, _) = funded(1_000_000_000);
+        sandbox.fund_account(user.account(), 1_000_000_000).unwrap();
+        let contract = deploy_adapter(&sandbox, owner);
+        let response = sandbox
+            .invoke(
+                contract.clone(),
+                "init",
+                vec![ScVal::Address(ScAddress::Account(owner.account()))],
+                None,
+                AuthMode::MockAll,
+                true,
+            )
+            .unwrap();
+        assert_eq!(response.error, None);
+
+        (sandbox, contract)
+    }
+
+    fn change_owner(contract: &ScAddress, owner: &Key) -> HostFunction {
+        HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: contract.clone(),
+            function_name: "change_owner".try_into().unwrap(),
+            args: vec![ScVal::Address(ScAddress::Account(owner.account()))]
+                .try_into()
+                .unwrap(),
+        })
+    }
+
+    #[test]
+    fn mocked_authorizations_stay_within_the_footprint() {
+        let (owner, user) = (Key::new(1), Key::new(2));
+        let (mut sandbox, contract) = owned_adapter(&owner, &user);
+
+        let mut envelope = simulated(&sandbox, &user, change_owner(&contract, &user));
+        user.sign(&sandbox, &mut envelope);
+        let response = sandbox
+            .send_transaction(TransactionEnvelope::Tx(envelope), Some(true))
+            .unwrap();
+        assert_eq!(response.base.status, SendTransactionStatus::Pending);
+        assert_eq!(response.required_auth.unwrap().len(), 1);
+
+        // The instance of the contract is read outside of the footprint.
+        let mut envelope = simulated(&sandbox, &user, change_owner(&contract, &owner));
+        let TransactionExt::V1(data) = &mut envelope.tx.ext else {
+            panic!("expected soroban data");
+        };
+        let instance = LedgerKey::ContractData(LedgerKeyContractData {
+            contract: contract.clone(),
+            key: ScVal::LedgerKeyContractInstance,
+            durability: ContractDataDurability::Persistent,
+        });
+        let footprint = &mut data.resources.footprint;
+        for keys in [&mut footprint.read_only, &mut footprint.read_write] {
+            *keys = keys
+                .iter()
+                .filter(|key| **key != instance)
+                .cloned()
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap();
+        }
+        user.sign(&sandbox, &mut envelope);
+        let response = sandbox
+            .send_transaction(TransactionEnvelope::Tx(envelope), Some(true))
+            .unwrap();
+        assert_eq!(response.base.status, SendTransactionStatus::Error);
+        let diagnostics = response.footprint_diagnostics.unwrap();
+        assert_eq!(
+            diagnostics.accessed_outside_footprint,
+            vec![instance.to_xdr_base64(Limits::none()).unwrap()]
+        );
+    }
+
+    #[test]
+    fn impersonation_verifies_other_addresses() {
+        let (owner, user) = (Key::new(1), Key::new(2));
+        let (mut sandbox, contract) = owned_adapter(&owner, &user);
+        sandbox.impersonate(ScAddress::Account(Key::new(9).account()));
+
+        for (signer, expected) in [
+            (None, SendTransactionStatus::Error),
+            (Some(Key::new(5)), SendTransactionStatus::Error),
+            (Some(owner), SendTransactionStatus::Pending),
+        ] {
+            let mut envelope = simulated(&sandbox, &user, change_owner(&contract, &user));
+            if let Some(signer) = &signer {
+                signer.sign_auth(&sandbox, &mut envelope);
+            }
+            user.sign(&sandbox, &mut envelope);
+            let response = send(&mut sandbox, envelope);
+            assert_eq!(response.base.status, expected);
+        }
+    }
+
+    /// Code written under the hash of other code, which only the cheat codes
+    /// can do.
+    fn code_entry(hash: Hash, wasm: Vec<u8>) -> LedgerEntry {
+        ledger_entry(LedgerEntryData::ContractCode(ContractCodeEntry {
+            ext: ContractCodeEntryExt::V0,
+            hash,
+            code: wasm.try_into().unwrap(),
+        }))
+    }
+
+    /// What the contract answers to a transaction sent by `key`.
+    fn answer(sandbox: &mut Sandbox, key: &Key, contract: &ScAddress) -> ScVal {
+        let host_fn = HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: contract.clone(),
+            function_name: "answer".try_into().unwrap(),
+            args: Default::default(),
+        });
+        let mut envelope = simulated(sandbox, key, host_fn);
+        key.sign(sandbox, &mut envelope);
+        let response = send(sandbox, envelope);
+        let GetTransactionResponse::Success(transaction) =
+            sandbox.get_transaction(response.base.hash).unwrap()
+        else {
+            panic!("transaction failed");
+        };
+
+        ScVal::from_xdr(transaction.return_value.unwrap(), Limits::none()).unwrap()
+    }
+
+    #[test]
+    fn forks_run_their_own_code() {
+        let (mut sandbox, key) = funded(1_000_000_000);
+        let hash = sandbox.upload_wasm(answer_contract(true)).unwrap();
+        let contract = sandbox
+            .deploy_contract(
+                hash.clone(),
+                ScAddress::Account(key.account()),
+                None,
+                vec![],
+            )
+            .unwrap();
+        assert_eq!(answer(&mut sandbox, &key, &contract), ScVal::Bool(true));
+
+        let mut fork = sandbox.fork();
+        fork.set_ledger_entry(code_entry(hash, answer_contract(false)), None)
+            .unwrap();
+        assert_eq!(answer(&mut fork, &key, &contract), ScVal::Bool(false));
+        assert_eq!(answer(&mut sandbox, &key, &contract), ScVal::Bool(true));
+        assert_eq!(answer(&mut fork, &key, &contract), ScVal::Bool(false));
+    }
+
+    #[test]
+    fn forks_keep_their_own_specs() {
+        let (sandbox, key) = funded(1_000_000_000);
+        let contract = deploy_adapter(&sandbox, &key);
+        assert!(sandbox.contract_spec(&contract).is_some());
+
+        let wasm = std::fs::read("test/redstone_adapter.wasm").unwrap();
+        let hash = sandbox.upload_wasm(wasm).unwrap();
+        let fork = sandbox.fork();
+        fork.set_ledger_entry(code_entry(hash, answer_contract(true)), None)
+            .unwrap();
+        assert!(fork.contract_spec(&contract).is_none());
+        assert!(sandbox.contract_spec(&contract).is_some());
+    }
+
+    #[test]
+    fn simulations_run_overridden_code() {
+        let (mut sandbox, key) = funded(1_000_000_000);
+        let hash = sandbox.upload_wasm(answer_contract(true)).unwrap();
+        let contract = sandbox
+            .deploy_contract(
+                hash.clone(),
+                ScAddress::Account(key.account()),
+                None,
+                vec![],
+            )
+            .unwrap();
+        assert_eq!(answer(&mut sandbox, &key, &contract), ScVal::Bool(true));
+
+        let host_fn = HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: contract.clone(),
+            function_name: "answer".try_into().unwrap(),
+            args: Default::default(),
+        });
+        let envelope = transaction(
+            &sandbox,
+            &key,
+            OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                host_function: host_fn,
+                auth: Default::default(),
+            }),
+        );
+        let simulated_answer = |overrides| {
+            let options = SimulateOptions {
+                overrides,
+                ..Default::default()
+            };
+            let response = sandbox
+                .simulate_transaction(TransactionEnvelope::Tx(envelope.clone()), options)
+                .unwrap();
+            let SimulateTransactionResponse::Success(simulated) = response else {
+                panic!("simulation failed");
+            };
+
+            ScVal::from_xdr_base64(&simulated.results.unwrap()[0].xdr, Limits::none()).unwrap()
+        };
+
+        let overrides = StateOverrides {
+            entries: vec![(code_entry(hash, answer_contract(false)), None)],
+            removed: vec![],
+        };
+        assert_eq!(simulated_answer(Some(overrides)), ScVal::Bool(false));
+        assert_eq!(simulated_answer(None), ScVal::Bool(true));
+        assert_eq!(answer(&mut sandbox, &key, &contract), ScVal::Bool(true));
+    }
 }
@@ -0,0 +1,387 @@
+//! Readable JSON form of Soroban values, events and ledger entries: addresses
+//! as strkeys, `u32` and `i32` as numbers, `i128` as decimal strings, symbols
+//! and strings as JSON strings, bytes as `0x` prefixed hex and maps as objects.
+//!
+//! [`json_to_scval`] infers the types back, so values which would be inferred
+//! as another type are written as `{"<type>": value}` objects, and decoding
+//! then encoding gives the same value back.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use serde_with::{DeserializeAs, SerializeAs};
+use soroban_env_host::{
+    xdr::{
+        ContractDataDurability, ContractEvent, ContractEventBody, ContractEventType,
+        ContractEventV0, ContractExecutable, ContractId, DiagnosticEvent, Duration, ExtensionPoint,
+        Int128Parts, Int256Parts, LedgerEntry, LedgerEntryData, ScAddress, ScMap, ScMapEntry,
+        ScString, ScSymbol, ScVal, TimePoint, UInt128Parts, UInt256Parts,
+    },
+    Error,
+};
+
+/// Types accepted by `{"<type>": value}` objects.
+const TYPES: [&str; 16] = [
+    "u32",
+    "i32",
+    "u64",
+    "i64",
+    "u128",
+    "i128",
+    "u256",
+    "i256",
+    "timepoint",
+    "duration",
+    "bytes",
+    "string",
+    "symbol",
+    "address",
+    "vec",
+    "map",
+];
+
+pub fn scval_to_json(value: &ScVal) -> Value {
+    match value {
+        ScVal::Bool(value) => Value::Bool(*value),
+        ScVal::Void => Value::Null,
+        ScVal::Error(error) => format!("{:?}", Error::from(error.clone())).into(),
+        ScVal::U32(value) => (*value).into(),
+        ScVal::I32(value) if *value < 0 => (*value).into(),
+        ScVal::I32(value) => json!({ "i32": value }),
+        ScVal::U64(value) => json!({ "u64": value.to_string() }),
+        ScVal::I64(value) => json!({ "i64": value.to_string() }),
+        ScVal::Timepoint(value) => json!({ "timepoint": value.0.to_string() }),
+        ScVal::Duration(value) => json!({ "duration": value.0.to_string() }),
+        ScVal::U128(parts) => json!({ "u128": parts.to_string() }),
+        ScVal::I128(parts) => parts.to_string().into(),
+        ScVal::U256(parts) => json!({ "u256": parts.to_string() }),
+        ScVal::I256(parts) => json!({ "i256": parts.to_string() }),
+        ScVal::Bytes(bytes) => format!("0x{}", hex::encode(bytes.as_slice())).into(),
+        ScVal::String(string) => text_to_json(value, "string", string.to_utf8_string_lossy()),
+        ScVal::Symbol(symbol) => text_to_json(value, "symbol", symbol.to_utf8_string_lossy()),
+        ScVal::Vec(None) | ScVal::Map(None) => Value::Null,
+        ScVal::Vec(Some(values)) => values.iter().map(scval_to_json).collect(),
+        ScVal::Map(Some(entries)) => map_to_json(entries),
+        ScVal::Address(address) => address.to_string().into(),
+        ScVal::ContractInstance(instance) => {
+            let executable = match &instance.executable {
+                ContractExecutable::Wasm(hash) => json!({ "wasm": hex::encode(hash.0) }),
+                ContractExecutable::StellarAsset => "stellarAsset".into(),
+            };
+            let storage = instance.storage.as_ref().map_or(Value::Null, map_to_json);
+
+            json!({ "executable": executable, "storage": storage })
+        },
+        ScVal::LedgerKeyContractInstance => "LedgerKeyContractInstance".into(),
+        ScVal::LedgerKeyNonce(key) => json!({ "nonce": key.nonce.to_string() }),
+    }
+}
+
+/// Strings and symbols which would be inferred as another type are wrapped.
+fn text_to_json(value: &ScVal, ty: &str, text: String) -> Value {
+    if string_to_scval(&text).is_ok_and(|inferred| inferred == *value) {
+        text.into()
+    } else {
+        json!({ ty: text })
+    }
+}
+
+/// Maps are objects when every key is a string inferred back as the same key,
+/// and `{"map": [[key, value], ...]}` otherwise.
+fn map_to_json(entries: &ScMap) -> Value {
+    let keys = entries
+        .iter()
+        .map(|entry| match scval_to_json(&entry.key) {
+            Value::String(key) if string_to_scval(&key).is_ok_and(|k| k == entry.key) => Some(key),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let is_typed = |keys: &[String]| keys.len() == 1 && TYPES.contains(&keys[0].as_str());
+
+    match keys {
+        Some(keys) if !is_typed(&keys) => {
+            let object = keys
+                .into_iter()
+                .zip(entries.iter())
+                .map(|(key, entry)| (key, scval_to_json(&entry.val)))
+                .collect::<Map<_, _>>();
+
+            Value::Object(object)
+        },
+        _ => {
+            let pairs = entries
+                .iter()
+                .map(|entry| json!([scval_to_json(&entry.key), scval_to_json(&entry.val)]))
+                .collect::<Vec<_>>();
+
+            json!({ "map": pairs })
+        },
+    }
+}
+
+/// Infers the type of each value:
+/// - integers are `u32`/`i32` when they fit and `u64`/`i64` otherwise,
+/// - strings are addresses when they are a strkey, bytes when `0x` prefixed
+///   hex, `i128` when decimal, symbols when made of `[a-zA-Z0-9_]` and
+///   strings otherwise,
+/// - arrays are vectors and objects are maps with their keys inferred the
+///   same way, unless the object is a single `{"<type>": value}` pair.
+///
+/// It is the inverse of [`scval_to_json`], except for errors, contract
+/// instances, ledger keys and missing vectors and maps, which can't be written
+/// back.
+pub fn json_to_scval(value: &Value) -> Result<ScVal> {
+    Ok(match value {
+        Value::Null => ScVal::Void,
+        Value::Bool(value) => ScVal::Bool(*value),
+        Value::Number(number) => {
+            if let Some(value) = number.as_u64() {
+                u32::try_from(value).map_or(ScVal::U64(value), ScVal::U32)
+            } else if let Some(value) = number.as_i64() {
+                i32::try_from(value).map_or(ScVal::I64(value), ScVal::I32)
+            } else {
+                bail!("{number} is not an integer")
+            }
+        },
+        Value::String(string) => string_to_scval(string)?,
+        Value::Array(values) => vec_to_scval(values)?,
+        Value::Object(object) => match object.iter().next() {
+            Some((ty, value)) if object.len() == 1 && TYPES.contains(&ty.as_str()) => {
+                typed_to_scval(ty, value)?
+            },
+            _ => object_to_scval(object)?,
+        },
+    })
+}
+
+fn string_to_scval(string: &str) -> Result<ScVal> {
+    if let Ok(address) = ScAddress::from_str(string) {
+        return Ok(ScVal::Address(address));
+    }
+    if let Some(bytes) = string
+        .strip_prefix("0x")
+        .and_then(|hex| hex::decode(hex).ok())
+    {
+        return Ok(ScVal::Bytes(bytes.try_into()?));
+    }
+    let digits = string.strip_prefix('-').unwrap_or(string);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        if let Ok(parts) = Int128Parts::from_str(string) {
+            return Ok(ScVal::I128(parts));
+        }
+    }
+    if is_symbol(string) {
+        return Ok(ScVal::Symbol(ScSymbol(string.try_into()?)));
+    }
+
+    Ok(ScVal::String(ScString(string.try_into()?)))
+}
+
+fn is_symbol(string: &str) -> bool {
+    !string.is_empty()
+        && string.len() <= 32
+        && string
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+fn vec_to_scval(values: &[Value]) -> Result<ScVal> {
+    let values = values
+        .iter()
+        .map(json_to_scval)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ScVal::Vec(Some(values.try_into()?)))
+}
+
+fn object_to_scval(object: &Map<String, Value>) -> Result<ScVal> {
+    let entries = object
+        .iter()
+        .map(|(key, val)| {
+            Ok(ScMapEntry {
+                key: string_to_scval(key)?,
+                val: json_to_scval(val)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ScVal::Map(Some(ScMap::sorted_from(entries)?)))
+}
+
+fn pairs_to_scval(pairs: &[Value]) -> Result<ScVal> {
+    let entries = pairs
+        .iter()
+        .map(|pair| match pair.as_array().map(Vec::as_slice) {
+            Some([key, val]) => Ok(ScMapEntry {
+                key: json_to_scval(key)?,
+                val: json_to_scval(val)?,
+            }),
+            _ => bail!("Invalid map entry {pair}, expected a [key, value] pair"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ScVal::Map(Some(ScMap::sorted_from(entries)?)))
+}
+
+/// Converts a value to one of the types of `{"<type>": value}` objects.
+pub fn typed_to_scval(ty: &str, value: &Value) -> Result<ScVal> {
+    let invalid = || anyhow!("Invalid {ty} value {value}");
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        Value::Array(values) if ty == "vec" => return vec_to_scval(values),
+        Value::Object(object) if ty == "map" => return object_to_scval(object),
+        Value::Array(pairs) if ty == "map" => return pairs_to_scval(pairs),
+        _ => return Err(invalid()),
+    };
+
+    Ok(match ty {
+        "u32" => ScVal::U32(text.parse().map_err(|_| invalid())?),
+        "i32" => ScVal::I32(text.parse().map_err(|_| invalid())?),
+        "u64" => ScVal::U64(text.parse().map_err(|_| invalid())?),
+        "i64" => ScVal::I64(text.parse().map_err(|_| invalid())?),
+        "timepoint" => ScVal::Timepoint(TimePoint(text.parse().map_err(|_| invalid())?)),
+        "duration" => ScVal::Duration(Duration(text.parse().map_err(|_| invalid())?)),
+        "u128" => ScVal::U128(UInt128Parts::from_str(&text).map_err(|_| invalid())?),
+        "i128" => ScVal::I128(Int128Parts::from_str(&text).map_err(|_| invalid())?),
+        "u256" => ScVal::U256(UInt256Parts::from_str(&text).map_err(|_| invalid())?),
+        "i256" => ScVal::I256(Int256Parts::from_str(&text).map_err(|_| invalid())?),
+        "bytes" => {
+            let hex = text.strip_prefix("0x").unwrap_or(&text);
+            ScVal::Bytes(hex::decode(hex).map_err(|_| invalid())?.try_into()?)
+        },
+        "string" => ScVal::String(ScString(text.as_str().try_into()?)),
+        "symbol" => ScVal::Symbol(ScSymbol(text.as_str().try_into()?)),
+        "address" => ScVal::Address(ScAddress::from_str(&text).map_err(|_| invalid())?),
+        _ => return Err(invalid()),
+    })
+}
+
+pub fn contract_event_to_json(event: &ContractEvent) -> Value {
+    let ContractEventBody::V0(body) = &event.body;
+    let mut json = json!({
+        "type": event_type_name(event.type_),
+        "topics": body.topics.iter().map(scval_to_json).collect::<Vec<_>>(),
+        "data": scval_to_json(&body.data),
+    });
+    if let Some(contract_id) = &event.contract_id {
+        json["contractId"] = contract_id.to_string().into();
+    }
+
+    json
+}
+
+pub fn diagnostic_event_to_json(event: &DiagnosticEvent) -> Value {
+    let mut json = contract_event_to_json(&event.event);
+    json["inSuccessfulContractCall"] = event.in_successful_contract_call.into();
+
+    json
+}
+
+fn event_type_name(event_type: ContractEventType) -> &'static str {
+    match event_type {
+        ContractEventType::Contract => "contract",
+        ContractEventType::System => "system",
+        ContractEventType::Diagnostic => "diagnostic",
+    }
+}
+
+fn json_to_contract_event(value: &Value) -> Result<ContractEvent> {
+    let type_ = match value["type"].as_str() {
+        Some("contract") => ContractEventType::Contract,
+        Some("system") => ContractEventType::System,
+        Some("diagnostic") => ContractEventType::Diagnostic,
+        _ => bail!("Invalid event type {}", value["type"]),
+    };
+    let contract_id = value["contractId"]
+        .as_str()
+        .map(ContractId::from_str)
+        .transpose()
+        .context("Invalid event contract id")?;
+    let topics = value["topics"]
+        .as_array()
+        .context("Event topics must be an array")?
+        .iter()
+        .map(json_to_scval)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ContractEvent {
+        ext: ExtensionPoint::V0,
+        contract_id,
+        type_,
+        body: ContractEventBody::V0(ContractEventV0 {
+            topics: topics.try_into()?,
+            data: json_to_scval(&value["data"])?,
+        }),
+    })
+}
+
+/// Contract data and code, accounts and TTLs are rendered readably, other
+/// entries keep their XDR JSON form.
+pub fn ledger_entry_to_json(entry: &LedgerEntry) -> Result<Value> {
+    let mut json = match &entry.data {
+        LedgerEntryData::ContractData(data) => json!({
+            "type": "contractData",
+            "contract": data.contract.to_string(),
+            "key": scval_to_json(&data.key),
+            "durability": match data.durability {
+                ContractDataDurability::Persistent => "persistent",
+                ContractDataDurability::Temporary => "temporary",
+            },
+            "val": scval_to_json(&data.val),
+        }),
+        LedgerEntryData::ContractCode(code) => json!({
+            "type": "contractCode",
+            "hash": hex::encode(code.hash.0),
+            "size": code.code.len(),
+        }),
+        LedgerEntryData::Account(account) => json!({
+            "type": "account",
+            "accountId": account.account_id.to_string(),
+            "balance": account.balance.to_string(),
+            "seqNum": account.seq_num.0.to_string(),
+            "numSubEntries": account.num_sub_entries,
+        }),
+        LedgerEntryData::Ttl(ttl) => json!({
+            "type": "ttl",
+            "keyHash": hex::encode(ttl.key_hash.0),
+            "liveUntilLedgerSeq": ttl.live_until_ledger_seq,
+        }),
+        data => serde_json::to_value(data)?,
+    };
+    json["lastModifiedLedgerSeq"] = entry.last_modified_ledger_seq.into();
+
+    Ok(json)
+}
+
+/// `serde_with` adapter writing values and events in their readable form.
+pub struct Readable;
+
+impl SerializeAs<ScVal> for Readable {
+    fn serialize_as<S: Serializer>(value: &ScVal, serializer: S) -> Result<S::Ok, S::Error> {
+        scval_to_json(value).serialize(serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, ScVal> for Readable {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<ScVal, D::Error> {
+        json_to_scval(&Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+impl SerializeAs<ContractEvent> for Readable {
+    fn serialize_as<S: Serializer>(
+        event: &ContractEvent,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        contract_event_to_json(event).serialize(serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, ContractEvent> for Readable {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<ContractEvent, D::Error> {
+        json_to_contract_event(&Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,71 @@
+//! Resource fees of what sent transactions and invocations used, rather than
+//! what they declared. Simulations get theirs from soroban-simulation.
+
+use std::rc::Rc;
+
+use anyhow::Result;
+use soroban_env_host::{
+    e2e_invoke::{extract_rent_changes, LedgerEntryChange},
+    fees::{compute_rent_fee, compute_transaction_resource_fee, TransactionResources},
+    storage::SnapshotSource,
+    xdr::{LedgerEntryData, LedgerKey, Limits, ReadXdr, WriteXdr},
+    LedgerInfo,
+};
+use soroban_simulation::NetworkConfig;
+
+/// Resource fee of the resources an invocation used, rather than the ones its
+/// transaction declared. Like for simulations, only the classic entries and
+/// the archived ones of `snapshot` are read from disk.
+pub fn resource_fee(
+    network_config: &NetworkConfig,
+    snapshot: &dyn SnapshotSource,
+    instructions: u64,
+    changes: &[LedgerEntryChange],
+    contract_events_size_bytes: u32,
+    transaction_size_bytes: u32,
+    ledger_info: &LedgerInfo,
+) -> Result<i64> {
+    let mut resources = TransactionResources {
+        instructions: instructions.try_into().unwrap_or(u32::MAX),
+        disk_read_entries: 0,
+        write_entries: 0,
+        disk_read_bytes: 0,
+        write_bytes: 0,
+        contract_events_size_bytes,
+        transaction_size_bytes,
+    };
+    for change in changes {
+        let key = LedgerKey::from_xdr(&change.encoded_key, Limits::none())?;
+        if let Some((entry, live_until)) = snapshot.get(&Rc::new(key))? {
+            let archived =
+                live_until.is_some_and(|live_until| live_until < ledger_info.sequence_number);
+            let soroban = matches!(
+                entry.data,
+                LedgerEntryData::ContractData(_) | LedgerEntryData::ContractCode(_)
+            );
+            if archived || !soroban {
+                resources.disk_read_entries += 1;
+                resources.disk_read_bytes += entry.to_xdr(Limits::none())?.len() as u32;
+            }
+        }
+        if !change.read_only {
+            resources.write_entries += 1;
+            resources.write_bytes += change
+                .encoded_new_value
+                .as_ref()
+                .map_or(0, |value| value.len() as u32);
+        }
+    }
+
+    let (non_refundable_fee, refundable_fee) =
+        compute_transaction_resource_fee(&resources, &network_config.fee_configuration);
+    let rent_fee = compute_rent_fee(
+        &extract_rent_changes(changes),
+        &network_config.rent_fee_configuration,
+        ledger_info.sequence_number,
+    );
+
+    Ok(non_refundable_fee
+        .saturating_add(refundable_fee)
+        .saturating_add(rent_fee))
+}
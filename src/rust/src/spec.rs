@@ -0,0 +1,499 @@
+//! Contract interfaces read from the `contractspecv0` custom section of
+//! uploaded Wasm: function signatures, user defined types and error enums.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Cursor,
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde_json::Value;
+use soroban_env_host::{
+    storage::SnapshotSource,
+    xdr::{
+        ContractCodeEntry, ContractDataDurability, ContractExecutable, ContractId, Hash,
+        LedgerEntryData, LedgerKey, LedgerKeyContractCode, LedgerKeyContractData, Limited, Limits,
+        ReadXdr, ScAddress, ScError, ScErrorType, ScMap, ScMapEntry, ScSpecEntry, ScSpecFunctionV0,
+        ScSpecTypeDef, ScSpecUdtUnionCaseV0, ScSymbol, ScVal,
+    },
+    Error,
+};
+
+use crate::{
+    memory::Memory,
+    scval_json::{json_to_scval, typed_to_scval},
+    utils::is_content_addressed,
+};
+
+const SPEC_SECTION: &str = "contractspecv0";
+
+#[derive(Debug, Clone, Default)]
+pub struct ContractSpec {
+    functions: BTreeMap<String, ScSpecFunctionV0>,
+    /// Structs, unions and enums by name.
+    types: BTreeMap<String, ScSpecEntry>,
+    /// Case names of the error enums by code.
+    errors: BTreeMap<u32, String>,
+}
+
+impl ContractSpec {
+    /// `None` when the module has no spec section.
+    pub fn from_wasm(wasm: &[u8]) -> Result<Option<Self>> {
+        let Some(section) = custom_section(wasm, SPEC_SECTION)? else {
+            return Ok(None);
+        };
+
+        let mut reader = Limited::new(Cursor::new(section), Limits::none());
+        let entries = ScSpecEntry::read_xdr_iter(&mut reader)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid contract spec")?;
+
+        Ok(Some(Self::from_entries(entries)))
+    }
+
+    pub fn from_entries(entries: impl IntoIterator<Item = ScSpecEntry>) -> Self {
+        let mut spec = Self::default();
+        for entry in entries {
+            match &entry {
+                ScSpecEntry::FunctionV0(function) => {
+                    spec.functions
+                        .insert(function.name.to_utf8_string_lossy(), function.clone());
+                },
+                ScSpecEntry::UdtStructV0(udt) => {
+                    spec.types.insert(udt.name.to_utf8_string_lossy(), entry);
+                },
+                ScSpecEntry::UdtUnionV0(udt) => {
+                    spec.types.insert(udt.name.to_utf8_string_lossy(), entry);
+                },
+                ScSpecEntry::UdtEnumV0(udt) => {
+                    spec.types.insert(udt.name.to_utf8_string_lossy(), entry);
+                },
+                ScSpecEntry::UdtErrorEnumV0(udt) => {
+                    for case in udt.cases.iter() {
+                        spec.errors
+                            .entry(case.value)
+                            .or_insert_with(|| case.name.to_utf8_string_lossy());
+                    }
+                    spec.types.insert(udt.name.to_utf8_string_lossy(), entry);
+                },
+                ScSpecEntry::EventV0(_) => {},
+            }
+        }
+
+        spec
+    }
+
+    pub fn function(&self, name: &str) -> Option<&ScSpecFunctionV0> {
+        self.functions.get(name)
+    }
+
+    pub fn arg_names(&self, function: &str) -> Option<Vec<String>> {
+        let function = self.function(function)?;
+
+        Some(
+            function
+                .inputs
+                .iter()
+                .map(|input| input.name.to_utf8_string_lossy())
+                .collect(),
+        )
+    }
+
+    /// `Error(Contract, #3) = PriceTooOld` for contract errors named by the
+    /// spec, `None` for any other error.
+    pub fn describe_error(&self, error: Error) -> Option<String> {
+        if !error.is_type(ScErrorType::Contract) {
+            return None;
+        }
+        let name = self.errors.get(&error.get_code())?;
+
+        Some(format!("{error:?} = {name}"))
+    }
+
+    /// Converts the arguments of `function`, given as an array in order or as
+    /// an object by name, where missing optional arguments are `None`.
+    pub fn args_from_json(&self, function: &str, args: &Value) -> Result<Vec<ScVal>> {
+        let spec = self
+            .function(function)
+            .with_context(|| format!("Function {function} is not in the contract spec"))?;
+
+        match args {
+            Value::Array(args) => {
+                ensure!(
+                    args.len() == spec.inputs.len(),
+                    "Function {function} takes {} arguments, got {}",
+                    spec.inputs.len(),
+                    args.len()
+                );
+
+                spec.inputs
+                    .iter()
+                    .zip(args)
+                    .map(|(input, arg)| self.json_to_scval(arg, &input.type_))
+                    .collect()
+            },
+            Value::Object(args) => {
+                let names = self.arg_names(function).unwrap_or_default();
+                if let Some(unknown) = args.keys().find(|name| !names.contains(name)) {
+                    bail!("Function {function} has no argument {unknown}");
+                }
+
+                spec.inputs
+                    .iter()
+                    .zip(&names)
+                    .map(|(input, name)| {
+                        let arg = args.get(name).unwrap_or(&Value::Null);
+                        self.json_to_scval(arg, &input.type_)
+                            .map_err(|e| anyhow!("Invalid argument {name}: {e}"))
+                    })
+                    .collect()
+            },
+            _ => bail!("Arguments must be an array or an object"),
+        }
+    }
+
+    /// Converts readable JSON to a value of the given type, user defined
+    /// types included: structs from objects (tuple structs from arrays),
+    /// unions from `"Case"` or `{"Case": value}` and enums from their case
+    /// name or value.
+    pub fn json_to_scval(&self, value: &Value, ty: &ScSpecTypeDef) -> Result<ScVal> {
+        let invalid = || anyhow!("Invalid {} value {value}", type_name(ty));
+
+        Ok(match ty {
+            ScSpecTypeDef::Val => json_to_scval(value)?,
+            ScSpecTypeDef::Bool => ScVal::Bool(value.as_bool().ok_or_else(invalid)?),
+            ScSpecTypeDef::Void => {
+                ensure!(value.is_null(), invalid());
+                ScVal::Void
+            },
+            ScSpecTypeDef::Error => {
+                let code = value.as_u64().and_then(|code| code.try_into().ok());
+                ScVal::Error(ScError::Contract(code.ok_or_else(invalid)?))
+            },
+            ScSpecTypeDef::U32 => typed_to_scval("u32", value)?,
+            ScSpecTypeDef::I32 => typed_to_scval("i32", value)?,
+            ScSpecTypeDef::U64 => typed_to_scval("u64", value)?,
+            ScSpecTypeDef::I64 => typed_to_scval("i64", value)?,
+            ScSpecTypeDef::Timepoint => typed_to_scval("timepoint", value)?,
+            ScSpecTypeDef::Duration => typed_to_scval("duration", value)?,
+            ScSpecTypeDef::U128 => typed_to_scval("u128", value)?,
+            ScSpecTypeDef::I128 => typed_to_scval("i128", value)?,
+            ScSpecTypeDef::U256 => typed_to_scval("u256", value)?,
+            ScSpecTypeDef::I256 => typed_to_scval("i256", value)?,
+            ScSpecTypeDef::Bytes => typed_to_scval("bytes", value)?,
+            ScSpecTypeDef::String => typed_to_scval("string", value)?,
+            ScSpecTypeDef::Symbol => typed_to_scval("symbol", value)?,
+            ScSpecTypeDef::Address | ScSpecTypeDef::MuxedAddress => {
+                typed_to_scval("address", value)?
+            },
+            ScSpecTypeDef::BytesN(bytes_n) => {
+                let bytes = typed_to_scval("bytes", value)?;
+                match &bytes {
+                    ScVal::Bytes(bytes) if bytes.len() == bytes_n.n as usize => {},
+                    _ => return Err(invalid()),
+                }
+                bytes
+            },
+            ScSpecTypeDef::Option(option) => match value {
+                Value::Null => ScVal::Void,
+                value => self.json_to_scval(value, &option.value_type)?,
+            },
+            ScSpecTypeDef::Result(_) => bail!("Result values can't be converted from JSON"),
+            ScSpecTypeDef::Vec(vec) => {
+                let values = value
+                    .as_array()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .map(|value| self.json_to_scval(value, &vec.element_type))
+                    .collect::<Result<Vec<_>>>()?;
+
+                ScVal::Vec(Some(values.try_into()?))
+            },
+            ScSpecTypeDef::Map(map) => {
+                let entries = value
+                    .as_object()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .map(|(key, val)| {
+                        Ok(ScMapEntry {
+                            key: self.json_to_scval(&Value::String(key.clone()), &map.key_type)?,
+                            val: self.json_to_scval(val, &map.value_type)?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                ScVal::Map(Some(ScMap::sorted_from(entries)?))
+            },
+            ScSpecTypeDef::Tuple(tuple) => {
+                let values = value.as_array().ok_or_else(invalid)?;
+                ensure!(values.len() == tuple.value_types.len(), invalid());
+                self.tuple_to_scval(values, &tuple.value_types, None)?
+            },
+            ScSpecTypeDef::Udt(udt) => {
+                self.udt_to_scval(value, &udt.name.to_utf8_string_lossy())?
+            },
+        })
+    }
+
+    fn udt_to_scval(&self, value: &Value, name: &str) -> Result<ScVal> {
+        let invalid = || anyhow!("Invalid {name} value {value}");
+        let udt = self
+            .types
+            .get(name)
+            .with_context(|| format!("Type {name} is not in the contract spec"))?;
+
+        Ok(match udt {
+            ScSpecEntry::UdtStructV0(udt) => {
+                let tuple = udt
+                    .fields
+                    .iter()
+                    .all(|field| field.name.to_utf8_string_lossy().parse::<u32>().is_ok());
+                let types = udt.fields.iter().map(|field| field.type_.clone());
+
+                if tuple {
+                    let values = value.as_array().ok_or_else(invalid)?;
+                    ensure!(values.len() == udt.fields.len(), invalid());
+                    self.tuple_to_scval(values, &types.collect::<Vec<_>>(), None)?
+                } else {
+                    let object = value.as_object().ok_or_else(invalid)?;
+                    let entries = udt
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            let name = field.name.to_utf8_string_lossy();
+                            let val = object.get(&name).unwrap_or(&Value::Null);
+
+                            Ok(ScMapEntry {
+                                key: ScVal::Symbol(ScSymbol(name.as_str().try_into()?)),
+                                val: self
+                                    .json_to_scval(val, &field.type_)
+                                    .map_err(|e| anyhow!("Invalid field {name}: {e}"))?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    ScVal::Map(Some(ScMap::sorted_from(entries)?))
+                }
+            },
+            ScSpecEntry::UdtUnionV0(udt) => {
+                let (case, val) = match value {
+                    Value::String(case) => (case, None),
+                    Value::Object(object) if object.len() == 1 => object
+                        .iter()
+                        .next()
+                        .map(|(case, val)| (case, Some(val)))
+                        .unwrap(),
+                    _ => return Err(invalid()),
+                };
+                let tag = ScVal::Symbol(ScSymbol(case.as_str().try_into()?));
+
+                match udt
+                    .cases
+                    .iter()
+                    .find(|c| union_case_name(c) == case.as_str())
+                {
+                    Some(ScSpecUdtUnionCaseV0::VoidV0(_)) if val.is_none() => {
+                        ScVal::Vec(Some(vec![tag].try_into()?))
+                    },
+                    Some(ScSpecUdtUnionCaseV0::TupleV0(tuple)) => {
+                        let val = val.ok_or_else(invalid)?;
+                        let values = match (val, tuple.type_.len()) {
+                            (val, 1) => std::slice::from_ref(val),
+                            (Value::Array(values), len) if values.len() == len => values,
+                            _ => return Err(invalid()),
+                        };
+
+                        self.tuple_to_scval(values, &tuple.type_, Some(tag))?
+                    },
+                    _ => return Err(invalid()),
+                }
+            },
+            ScSpecEntry::UdtEnumV0(udt) => {
+                let cases = udt.cases.iter().map(|case| (&case.name, case.value));
+                ScVal::U32(enum_value(cases, value).ok_or_else(invalid)?)
+            },
+            ScSpecEntry::UdtErrorEnumV0(udt) => {
+                let cases = udt.cases.iter().map(|case| (&case.name, case.value));
+                ScVal::Error(ScError::Contract(
+                    enum_value(cases, value).ok_or_else(invalid)?,
+                ))
+            },
+            ScSpecEntry::FunctionV0(_) | ScSpecEntry::EventV0(_) => return Err(invalid()),
+        })
+    }
+
+    /// Vector of the values converted to their types, after `tag` if any.
+    fn tuple_to_scval(
+        &self,
+        values: &[Value],
+        types: &[ScSpecTypeDef],
+        tag: Option<ScVal>,
+    ) -> Result<ScVal> {
+        let values = values
+            .iter()
+            .zip(types)
+            .map(|(value, ty)| self.json_to_scval(value, ty));
+        let values = tag
+            .into_iter()
+            .map(Ok)
+            .chain(values)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(ScVal::Vec(Some(values.try_into()?)))
+    }
+}
+
+fn union_case_name(case: &ScSpecUdtUnionCaseV0) -> String {
+    match case {
+        ScSpecUdtUnionCaseV0::VoidV0(case) => case.name.to_utf8_string_lossy(),
+        ScSpecUdtUnionCaseV0::TupleV0(case) => case.name.to_utf8_string_lossy(),
+    }
+}
+
+/// Value of the enum case given by name or value.
+fn enum_value<'a, const MAX: u32>(
+    mut cases: impl Iterator<Item = (&'a soroban_env_host::xdr::StringM<MAX>, u32)>,
+    value: &Value,
+) -> Option<u32> {
+    match value {
+        Value::String(name) => cases
+            .find(|(case, _)| case.to_utf8_string_lossy() == *name)
+            .map(|(_, value)| value),
+        value => {
+            let value = u32::try_from(value.as_u64()?).ok()?;
+            cases.any(|(_, case)| case == value).then_some(value)
+        },
+    }
+}
+
+fn type_name(ty: &ScSpecTypeDef) -> String {
+    match ty {
+        ScSpecTypeDef::Udt(udt) => udt.name.to_utf8_string_lossy(),
+        ty => ty.name().to_lowercase(),
+    }
+}
+
+/// Payload of the custom section called `name`, `None` when the module has
+/// none.
+fn custom_section<'a>(wasm: &'a [u8], name: &str) -> Result<Option<&'a [u8]>> {
+    ensure!(wasm.starts_with(b"\0asm"), "Not a Wasm module");
+    let mut rest = wasm.get(8..).context("Truncated Wasm module")?;
+
+    while let Some((&id, tail)) = rest.split_first() {
+        rest = tail;
+        let size = read_leb128(&mut rest)? as usize;
+        ensure!(size <= rest.len(), "Truncated Wasm section");
+        let (mut section, tail) = rest.split_at(size);
+        rest = tail;
+
+        if id == 0 {
+            let len = read_leb128(&mut section)? as usize;
+            ensure!(len <= section.len(), "Truncated Wasm custom section");
+            let (section_name, payload) = section.split_at(len);
+            if section_name == name.as_bytes() {
+                return Ok(Some(payload));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_leb128(bytes: &mut &[u8]) -> Result<u32> {
+    let mut value = 0;
+    for shift in (0..32).step_by(7) {
+        let (&byte, rest) = bytes.split_first().context("Truncated Wasm module")?;
+        *bytes = rest;
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    bail!("Invalid LEB128 integer in Wasm module")
+}
+
+/// Specs of the uploaded Wasm by hash, parsed when the code is written. Code
+/// loaded without being written, like the one of a forked network, is parsed
+/// the first time it's needed. Only code matching its hash is indexed, so a
+/// spec is the same for every state that has its code and the index is shared
+/// by all of them. Other code is parsed every time.
+#[derive(Debug, Default)]
+pub struct SpecIndex {
+    specs: Mutex<HashMap<Hash, Option<Arc<ContractSpec>>>>,
+}
+
+impl SpecIndex {
+    pub fn add(&self, code: &ContractCodeEntry) {
+        if is_content_addressed(code) && !self.specs().contains_key(&code.hash) {
+            self.specs().insert(code.hash.clone(), parse_spec(code));
+        }
+    }
+
+    pub fn wasm_spec(&self, memory: &Memory, hash: &Hash) -> Option<Arc<ContractSpec>> {
+        let key = LedgerKey::ContractCode(LedgerKeyContractCode { hash: hash.clone() });
+        let (entry, _) = memory.get(&Rc::new(key)).ok()??;
+        let LedgerEntryData::ContractCode(code) = &entry.data else {
+            return None;
+        };
+        if !is_content_addressed(code) {
+            return parse_spec(code);
+        }
+        self.add(code);
+
+        self.specs().get(hash).cloned().flatten()
+    }
+
+    /// Spec of the Wasm a contract instance runs, `None` for Stellar asset
+    /// contracts.
+    pub fn contract_spec(
+        &self,
+        memory: &Memory,
+        contract: &ContractId,
+    ) -> Option<Arc<ContractSpec>> {
+        let key = LedgerKey::ContractData(LedgerKeyContractData {
+            contract: ScAddress::Contract(contract.clone()),
+            key: ScVal::LedgerKeyContractInstance,
+            durability: ContractDataDurability::Persistent,
+        });
+        let (entry, _) = memory.get(&Rc::new(key)).ok()??;
+
+        match &entry.data {
+            LedgerEntryData::ContractData(data) => match &data.val {
+                ScVal::ContractInstance(instance) => match &instance.executable {
+                    ContractExecutable::Wasm(hash) => self.wasm_spec(memory, hash),
+                    ContractExecutable::StellarAsset => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn specs(&self) -> MutexGuard<'_, HashMap<Hash, Option<Arc<ContractSpec>>>> {
+        self.specs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn parse_spec(code: &ContractCodeEntry) -> Option<Arc<ContractSpec>> {
+    ContractSpec::from_wasm(code.code.as_slice())
+        .ok()
+        .flatten()
+        .map(Arc::new)
+}
+
+/// Arguments given as JSON, converted per the spec of the contract when it
+/// has one and by inferring their types otherwise.
+pub fn args_from_json(
+    spec: Option<&ContractSpec>,
+    function: &str,
+    args: &Value,
+) -> Result<Vec<ScVal>> {
+    match (spec, args) {
+        (Some(spec), args) => spec.args_from_json(function, args),
+        (None, Value::Array(args)) => args.iter().map(json_to_scval).collect(),
+        (None, _) => bail!("Arguments must be an array for contracts without a spec"),
+    }
+}
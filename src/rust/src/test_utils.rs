@@ -0,0 +1,207 @@
+//! Accounts and transactions shared by the unit tests.
+
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+use soroban_env_host::xdr::{
+    AccountId, BumpSequenceOp, DecoratedSignature, Hash, HashIdPreimage,
+    HashIdPreimageSorobanAuthorization, HostFunction, InvokeHostFunctionOp, Limits, Memo,
+    MuxedAccount, Operation, OperationBody, Preconditions, PublicKey, ReadXdr, ScAddress,
+    ScEnvMetaEntry, ScEnvMetaEntryInterfaceVersion, ScMap, ScMapEntry, ScVal, SequenceNumber,
+    Signature, SignatureHint, SorobanAuthorizationEntry, SorobanCredentials,
+    SorobanTransactionData, Transaction, TransactionEnvelope, TransactionExt,
+    TransactionV1Envelope, Uint256, WriteXdr,
+};
+
+use crate::{
+    model::{SimulateOptions, SimulateTransactionResponse},
+    sandbox::Sandbox,
+    utils::tx_hash,
+};
+
+/// Ed25519 account derived from a one byte seed.
+pub struct Key(SigningKey);
+
+impl Key {
+    pub fn new(seed: u8) -> Self {
+        Self(SigningKey::from_bytes(&[seed; 32]))
+    }
+
+    pub fn account(&self) -> AccountId {
+        AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(
+            self.0.verifying_key().to_bytes(),
+        )))
+    }
+
+    /// Signs the transaction for the ledger the sandbox is at.
+    pub fn sign(&self, sandbox: &Sandbox, envelope: &mut TransactionV1Envelope) {
+        let hash = tx_hash(envelope, sandbox.get_ledger_info()).unwrap();
+        let public_key = self.0.verifying_key().to_bytes();
+
+        let signature = DecoratedSignature {
+            hint: SignatureHint(public_key[28..].try_into().unwrap()),
+            signature: Signature(self.0.sign(&hash).to_bytes().to_vec().try_into().unwrap()),
+        };
+        envelope.signatures = vec![signature].try_into().unwrap();
+    }
+
+    /// Signs the authorization entries of the invocation for this account.
+    pub fn sign_auth(&self, sandbox: &Sandbox, envelope: &mut TransactionV1Envelope) {
+        let ledger_info = sandbox.get_ledger_info();
+        let public_key = self.0.verifying_key().to_bytes();
+
+        let mut operations = envelope.tx.operations.to_vec();
+        let OperationBody::InvokeHostFunction(op) = &mut operations[0].body else {
+            panic!("expected an invocation");
+        };
+        let mut auth = op.auth.to_vec();
+        for entry in &mut auth {
+            let SorobanCredentials::Address(credentials) = &mut entry.credentials else {
+                continue;
+            };
+            credentials.signature_expiration_ledger = ledger_info.sequence_number + 100;
+            let preimage =
+                HashIdPreimage::SorobanAuthorization(HashIdPreimageSorobanAuthorization {
+                    network_id: Hash(ledger_info.network_id),
+                    nonce: credentials.nonce,
+                    signature_expiration_ledger: credentials.signature_expiration_ledger,
+                    invocation: entry.root_invocation.clone(),
+                });
+            let payload = Sha256::digest(preimage.to_xdr(Limits::none()).unwrap());
+            let signature = ScMap(
+                vec![
+                    ScMapEntry {
+                        key: ScVal::Symbol("public_key".try_into().unwrap()),
+                        val: ScVal::Bytes(public_key.to_vec().try_into().unwrap()),
+                    },
+                    ScMapEntry {
+                        key: ScVal::Symbol("signature".try_into().unwrap()),
+                        val: ScVal::Bytes(self.0.sign(&payload).to_vec().try_into().unwrap()),
+                    },
+                ]
+                .try_into()
+                .unwrap(),
+            );
+            credentials.signature =
+                ScVal::Vec(Some(vec![ScVal::Map(Some(signature))].try_into().unwrap()));
+        }
+        op.auth = auth.try_into().unwrap();
+        envelope.tx.operations = operations.try_into().unwrap();
+    }
+}
+
+/// Unsigned transaction of `key` with the next sequence number.
+pub fn transaction(sandbox: &Sandbox, key: &Key, body: OperationBody) -> TransactionV1Envelope {
+    let seq_num = sandbox.get_account(key.account()).unwrap().seq_num.0 + 1;
+
+    TransactionV1Envelope {
+        tx: Transaction {
+            source_account: MuxedAccount::Ed25519(Uint256(key.0.verifying_key().to_bytes())),
+            fee: 1_000_000,
+            seq_num: SequenceNumber(seq_num),
+            cond: Preconditions::None,
+            memo: Memo::None,
+            operations: vec![Operation {
+                source_account: None,
+                body,
+            }]
+            .try_into()
+            .unwrap(),
+            ext: TransactionExt::V0,
+        },
+        signatures: Default::default(),
+    }
+}
+
+/// Unsigned invocation of `key` with the footprint and authorization entries
+/// of its simulation.
+pub fn simulated(
+    sandbox: &Sandbox,
+    key: &Key,
+    host_function: HostFunction,
+) -> TransactionV1Envelope {
+    let mut envelope = transaction(
+        sandbox,
+        key,
+        OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+            host_function: host_function.clone(),
+            auth: Default::default(),
+        }),
+    );
+    let simulation = sandbox
+        .simulate_transaction(
+            TransactionEnvelope::Tx(envelope.clone()),
+            SimulateOptions::default(),
+        )
+        .unwrap();
+    let simulation = match simulation {
+        SimulateTransactionResponse::Success(simulation) => simulation,
+        SimulateTransactionResponse::Error(error) => panic!("simulation failed: {}", error.error),
+    };
+
+    let data =
+        SorobanTransactionData::from_xdr_base64(&simulation.transaction_data, Limits::none())
+            .unwrap();
+    let auth = simulation.results.unwrap()[0]
+        .auth
+        .iter()
+        .map(|entry| SorobanAuthorizationEntry::from_xdr_base64(entry, Limits::none()).unwrap())
+        .collect::<Vec<_>>();
+    envelope.tx.fee += data.resource_fee as u32;
+    envelope.tx.ext = TransactionExt::V1(data);
+    envelope.tx.operations = vec![Operation {
+        source_account: None,
+        body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+            host_function,
+            auth: auth.try_into().unwrap(),
+        }),
+    }]
+    .try_into()
+    .unwrap();
+
+    envelope
+}
+
+pub fn bump_sequence(sandbox: &Sandbox, key: &Key) -> TransactionV1Envelope {
+    transaction(
+        sandbox,
+        key,
+        OperationBody::BumpSequence(BumpSequenceOp {
+            bump_to: SequenceNumber(0),
+        }),
+    )
+}
+
+/// Deploys the RedStone adapter the TypeScript tests use, deployed by `owner`.
+pub fn deploy_adapter(sandbox: &Sandbox, owner: &Key) -> ScAddress {
+    let wasm = std::fs::read("test/redstone_adapter.wasm").unwrap();
+    let hash = sandbox.upload_wasm(wasm).unwrap();
+
+    sandbox
+        .deploy_contract(hash, ScAddress::Account(owner.account()), None, vec![])
+        .unwrap()
+}
+
+/// Wasm of a contract whose `answer` function returns `answer`, to tell the
+/// code an instance runs by its behavior.
+pub fn answer_contract(answer: bool) -> Vec<u8> {
+    let meta = ScEnvMetaEntry::ScEnvMetaKindInterfaceVersion(ScEnvMetaEntryInterfaceVersion {
+        protocol: 23,
+        pre_release: 0,
+    })
+    .to_xdr(Limits::none())
+    .unwrap();
+    let name = b"contractenvmetav0";
+    let section = |id: u8, content: &[u8]| [&[id, content.len() as u8], content].concat();
+
+    [
+        b"\0asm\x01\0\0\0".to_vec(),
+        // () -> i64
+        section(1, &[1, 0x60, 0, 1, 0x7e]),
+        section(3, &[1, 0]),
+        section(7, &[&[1, 6], &b"answer"[..], &[0, 0]].concat()),
+        // i64.const answer, the Val of a bool being the bool itself
+        section(10, &[1, 4, 0, 0x42, answer as u8, 0x0b]),
+        section(0, &[&[name.len() as u8], &name[..], &meta].concat()),
+    ]
+    .concat()
+}
@@ -0,0 +1,533 @@
+//! Call trees of contract invocations, rebuilt from the `fn_call` and
+//! `fn_return` diagnostic events of the host.
+
+use std::{cell::RefCell, fmt, rc::Rc, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use soroban_env_host::{
+    budget::Budget,
+    e2e_invoke::RecordingInvocationAuthMode,
+    storage::{SnapshotSource, Storage},
+    xdr::{
+        AccountId, ContractEvent, ContractEventBody, ContractId, DiagnosticEvent, Hash,
+        HostFunction, ScAddress, ScSymbol, ScVal,
+    },
+    Error, Host, HostError, LedgerInfo, TraceEvent, TraceHook,
+};
+
+use crate::{
+    scval_json::{scval_to_json, Readable},
+    spec::ContractSpec,
+};
+
+/// A contract call, with the calls it made in order. Values and events are
+/// serialized in their readable JSON form.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvocationFrame {
+    /// Strkey of the called contract.
+    pub contract: String,
+    pub function: String,
+    #[serde_as(as = "Vec<Readable>")]
+    pub args: Vec<ScVal>,
+    /// Names of the arguments, when the contract has a spec.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arg_names: Vec<String>,
+    /// Returned value, missing when the call failed.
+    #[serde_as(as = "Option<Readable>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ScVal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Events emitted by the call itself, not by its sub-calls.
+    #[serde_as(as = "Vec<Readable>")]
+    pub events: Vec<ContractEvent>,
+    /// Budget spent while the frame was on the stack, sub-calls included.
+    /// Calls metered by the host in recording mode also count instantiating
+    /// their contract.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_insns: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_bytes: Option<u64>,
+    pub calls: Vec<InvocationFrame>,
+}
+
+/// Top level calls of an invocation, with their pretty-printed form.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvocationTrace {
+    pub calls: Vec<InvocationFrame>,
+    pub text: String,
+}
+
+impl InvocationTrace {
+    /// Rebuilds the call tree from the diagnostic events of an invocation,
+    /// `costs` are matched in order to the calls that entered their contract. The specs of
+    /// the called contracts name their arguments and errors.
+    pub fn from_events(
+        events: &[DiagnosticEvent],
+        costs: &[FrameCost],
+        specs: &dyn Fn(&ContractId) -> Option<Arc<ContractSpec>>,
+    ) -> Self {
+        let mut builder = TraceBuilder {
+            stack: vec![],
+            calls: vec![],
+            costs: costs.iter().peekable(),
+            entering: false,
+            specs,
+        };
+        for event in events {
+            builder.record(&event.event);
+        }
+        builder.entering = false;
+        while !builder.stack.is_empty() {
+            builder.close_failed();
+        }
+
+        let text = builder
+            .calls
+            .iter()
+            .map(|frame| frame.to_string())
+            .collect();
+
+        Self {
+            calls: builder.calls,
+            text,
+        }
+    }
+}
+
+struct TraceBuilder<'a> {
+    stack: Vec<(ContractId, InvocationFrame)>,
+    calls: Vec<InvocationFrame>,
+    costs: std::iter::Peekable<std::slice::Iter<'a, FrameCost>>,
+    /// Whether the top frame is called but not known to be entered yet, a
+    /// call can fail before the contract frame is pushed.
+    entering: bool,
+    specs: &'a dyn Fn(&ContractId) -> Option<Arc<ContractSpec>>,
+}
+
+impl TraceBuilder<'_> {
+    fn record(&mut self, event: &ContractEvent) {
+        let ContractEventBody::V0(body) = &event.body;
+
+        // Every event of a call that entered its contract is emitted by it,
+        // starting with its own calls, logs or return.
+        if std::mem::take(&mut self.entering) {
+            let depth = self.stack.len() - 1;
+            if let Some((id, frame)) = self.stack.last_mut() {
+                if Some(&*id) == event.contract_id.as_ref() {
+                    // Calls metered by the host can fail before entering
+                    // their contract, their costs are skipped.
+                    while self
+                        .costs
+                        .next_if(|cost| {
+                            cost.call.is_some()
+                                && (cost.depth > depth
+                                    || (cost.depth == depth && !cost.is_call(id, &frame.function)))
+                        })
+                        .is_some()
+                    {}
+                    if let Some(cost) = self.costs.next_if(|cost| cost.depth == depth) {
+                        frame.cpu_insns = Some(cost.cpu_insns);
+                        frame.mem_bytes = Some(cost.mem_bytes);
+                    }
+                }
+            }
+        }
+
+        match body.topics.as_slice() {
+            [ScVal::Symbol(name), ScVal::Bytes(id), ScVal::Symbol(function)]
+                if name.as_slice() == b"fn_call" =>
+            {
+                let Ok(id) = <[u8; 32]>::try_from(id.as_slice()) else {
+                    return;
+                };
+
+                // A failed call never returns, so every frame above the caller
+                // is done by the time it calls again.
+                while let Some((top, _)) = self.stack.last() {
+                    if Some(top) == event.contract_id.as_ref() {
+                        break;
+                    }
+                    self.close_failed();
+                }
+                self.open(
+                    ContractId(Hash(id)),
+                    function.to_utf8_string_lossy(),
+                    &body.data,
+                );
+            },
+            [ScVal::Symbol(name), ScVal::Symbol(function)] if name.as_slice() == b"fn_return" => {
+                let function = function.to_utf8_string_lossy();
+                let Some(position) = self.stack.iter().rposition(|(id, frame)| {
+                    Some(id) == event.contract_id.as_ref() && frame.function == function
+                }) else {
+                    return;
+                };
+
+                while self.stack.len() > position + 1 {
+                    self.close_failed();
+                }
+                if let Some((_, mut frame)) = self.stack.pop() {
+                    frame.result = Some(body.data.clone());
+                    self.close(frame);
+                }
+            },
+            topics => {
+                let Some((id, frame)) = self.stack.last_mut() else {
+                    return;
+                };
+                if let [ScVal::Symbol(name), ScVal::Error(error)] = topics {
+                    if name.as_slice() == b"error" && frame.error.is_none() {
+                        let spec = (self.specs)(id);
+                        frame.error = Some(error_message(error, &body.data, spec.as_deref()));
+                    }
+                }
+                frame.events.push(event.clone());
+            },
+        }
+    }
+
+    fn open(&mut self, id: ContractId, function: String, data: &ScVal) {
+        let arg_names = (self.specs)(&id)
+            .and_then(|spec| spec.arg_names(&function))
+            .unwrap_or_default();
+
+        // Arguments are reported as a vector unless there is exactly one, so a
+        // single vector argument can only be told apart from several arguments
+        // with the spec.
+        let args = match data {
+            arg if arg_names.len() == 1 => vec![arg.clone()],
+            ScVal::Void => vec![],
+            ScVal::Vec(Some(args)) => args.to_vec(),
+            arg => vec![arg.clone()],
+        };
+
+        let frame = InvocationFrame {
+            contract: ScAddress::Contract(id.clone()).to_string(),
+            function,
+            args,
+            arg_names,
+            result: None,
+            error: None,
+            events: vec![],
+            cpu_insns: None,
+            mem_bytes: None,
+            calls: vec![],
+        };
+        self.stack.push((id, frame));
+        self.entering = true;
+    }
+
+    fn close_failed(&mut self) {
+        if let Some((_, mut frame)) = self.stack.pop() {
+            if frame.error.is_none() {
+                frame.error = frame
+                    .calls
+                    .iter()
+                    .rev()
+                    .find_map(|call| call.error.clone())
+                    .or_else(|| Some("call did not return".to_string()));
+            }
+            self.close(frame);
+        }
+    }
+
+    fn close(&mut self, frame: InvocationFrame) {
+        match self.stack.last_mut() {
+            Some((_, parent)) => parent.calls.push(frame),
+            None => self.calls.push(frame),
+        }
+    }
+}
+
+fn error_message(
+    error: &soroban_env_host::xdr::ScError,
+    data: &ScVal,
+    spec: Option<&ContractSpec>,
+) -> String {
+    let error = Error::from(error.clone());
+    let error = spec
+        .and_then(|spec| spec.describe_error(error))
+        .unwrap_or_else(|| format!("{error:?}"));
+    let message = match data {
+        ScVal::String(message) => Some(message.to_utf8_string_lossy()),
+        ScVal::Vec(Some(values)) => values.first().and_then(|value| match value {
+            ScVal::String(message) => Some(message.to_utf8_string_lossy()),
+            _ => None,
+        }),
+        _ => None,
+    };
+
+    match message {
+        Some(message) => format!("{error}: {message}"),
+        None => error,
+    }
+}
+
+/// Line printed for a `log` diagnostic event emitted by a contract, `None` for
+/// any other event.
+pub fn log_message(event: &DiagnosticEvent) -> Option<String> {
+    let ContractEventBody::V0(body) = &event.event.body;
+    match body.topics.as_slice() {
+        [ScVal::Symbol(topic)] if topic.as_slice() == b"log" => {},
+        _ => return None,
+    }
+
+    let (message, args) = match &body.data {
+        ScVal::String(message) => (message.to_utf8_string_lossy(), &[][..]),
+        ScVal::Vec(Some(values)) => match values.split_first() {
+            Some((ScVal::String(message), args)) => (message.to_utf8_string_lossy(), args),
+            _ => (String::new(), values.as_slice()),
+        },
+        other => (format_val(other), &[][..]),
+    };
+
+    let mut line = match &event.event.contract_id {
+        Some(contract) => format!("{}: {message}", ScAddress::Contract(contract.clone())),
+        None => message,
+    };
+    for arg in args {
+        line.push(' ');
+        line.push_str(&format_val(arg));
+    }
+
+    Some(line)
+}
+
+/// Budget spent by a contract frame, in the order the frames were pushed.
+#[derive(Debug, Clone)]
+pub struct FrameCost {
+    depth: usize,
+    /// Debug form of the call metered by the host, which keeps its invocation
+    /// type private. Frames recorded through the trace hook have none.
+    call: Option<String>,
+    pub cpu_insns: u64,
+    pub mem_bytes: u64,
+}
+
+impl FrameCost {
+    /// Cost of an invocation metered by the host, `None` when it is not a
+    /// contract call.
+    pub fn metered(
+        depth: usize,
+        invocation: &impl fmt::Debug,
+        cpu_insns: i64,
+        mem_bytes: i64,
+    ) -> Option<Self> {
+        let call = format!("{invocation:?}");
+        call.starts_with("InvokeContract(").then(|| Self {
+            depth,
+            call: Some(call),
+            cpu_insns: cpu_insns.max(0) as u64,
+            mem_bytes: mem_bytes.max(0) as u64,
+        })
+    }
+
+    fn is_call(&self, contract: &ContractId, function: &str) -> bool {
+        let Some(call) = &self.call else {
+            return true;
+        };
+        let Ok(function) = ScSymbol::try_from(function) else {
+            return false;
+        };
+
+        *call
+            == format!(
+                "InvokeContract({:?}, {:?})",
+                ScAddress::Contract(contract.clone()),
+                function
+            )
+    }
+}
+
+/// Records the budget spent by each contract frame through the host trace
+/// hook.
+#[derive(Default)]
+pub struct FrameCostRecorder {
+    frames: Vec<FrameCost>,
+    open: Vec<(usize, u64, u64)>,
+}
+
+impl FrameCostRecorder {
+    pub fn hook(recorder: Rc<RefCell<Self>>) -> TraceHook {
+        Rc::new(move |host, event| {
+            let push = match event {
+                TraceEvent::PushCtx(..) => true,
+                TraceEvent::PopCtx(..) => false,
+                _ => return Ok(()),
+            };
+
+            let budget = host.budget_cloned();
+            let cpu_insns = budget.get_cpu_insns_consumed()?;
+            let mem_bytes = budget.get_mem_bytes_consumed()?;
+
+            let mut recorder = recorder.borrow_mut();
+            if push {
+                let (depth, index) = (recorder.open.len(), recorder.frames.len());
+                recorder.open.push((index, cpu_insns, mem_bytes));
+                recorder.frames.push(FrameCost {
+                    depth,
+                    call: None,
+                    cpu_insns: 0,
+                    mem_bytes: 0,
+                });
+            } else if let Some((index, cpu_start, mem_start)) = recorder.open.pop() {
+                let frame = &mut recorder.frames[index];
+                frame.cpu_insns = cpu_insns - cpu_start;
+                frame.mem_bytes = mem_bytes - mem_start;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Costs of the contract frames, without the host function frame at the
+    /// bottom of the stack.
+    pub fn into_costs(self) -> Vec<FrameCost> {
+        self.frames
+            .into_iter()
+            .filter(|frame| frame.depth > 0)
+            .map(|frame| FrameCost {
+                depth: frame.depth - 1,
+                ..frame
+            })
+            .collect()
+    }
+}
+
+/// Costs of the contract frames of a recording mode run, replayed with the
+/// invocation metered by the host since only the enforcing mode takes a trace
+/// hook. No module cache is set, so contracts are parsed and charged the same
+/// as by `e2e_invoke::invoke_host_function_in_recording_mode`.
+pub fn replay_frame_costs(
+    host_fn: &HostFunction,
+    source_account: &AccountId,
+    auth_mode: &RecordingInvocationAuthMode,
+    ledger_info: &LedgerInfo,
+    snapshot: Rc<dyn SnapshotSource>,
+    base_prng_seed: [u8; 32],
+) -> Result<Vec<FrameCost>, HostError> {
+    let budget = Budget::default();
+    budget.reset_unlimited()?;
+    let host = Host::with_storage_and_budget(Storage::with_recording_footprint(snapshot), budget);
+    host.set_source_account(source_account.clone())?;
+    host.set_ledger_info(ledger_info.clone())?;
+    host.set_base_prng_seed(base_prng_seed)?;
+    match auth_mode {
+        RecordingInvocationAuthMode::Enforcing(entries) => {
+            host.set_authorization_entries(entries.clone())?;
+        },
+        RecordingInvocationAuthMode::Recording(disable_non_root_auth) => {
+            host.switch_to_recording_auth(*disable_non_root_auth)?;
+        },
+    }
+    host.enable_invocation_metering();
+    // Failures are reported by the run being replayed, the frames up to the
+    // failure are still metered.
+    let _ = host.invoke_function(host_fn.clone());
+
+    // The host function is metered as the contract call it makes, other host
+    // functions only through the calls made from them.
+    let mut frame_costs = vec![];
+    let Some(root) = host.get_detailed_last_invocation_resources() else {
+        return Ok(frame_costs);
+    };
+    let mut invocations = vec![(&root, 0)];
+    while let Some((invocation, depth)) = invocations.pop() {
+        let depth = match FrameCost::metered(
+            depth,
+            &invocation.invocation,
+            invocation.resources.instructions,
+            invocation.resources.mem_bytes,
+        ) {
+            Some(cost) => {
+                frame_costs.push(cost);
+                depth + 1
+            },
+            None => depth,
+        };
+        invocations.extend(
+            invocation
+                .sub_call_resources
+                .iter()
+                .rev()
+                .map(|sub_call| (sub_call, depth)),
+        );
+    }
+
+    Ok(frame_costs)
+}
+
+impl fmt::Display for InvocationFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_tree(f, "", "")
+    }
+}
+
+impl InvocationFrame {
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, first: &str, rest: &str) -> fmt::Result {
+        let args = self.args.iter().map(format_val);
+        let args = match self.arg_names.len() == self.args.len() {
+            true => self
+                .arg_names
+                .iter()
+                .zip(args)
+                .map(|(name, arg)| format!("{name}: {arg}"))
+                .collect::<Vec<_>>(),
+            false => args.collect(),
+        };
+        write!(
+            f,
+            "{first}{}.{}({})",
+            self.contract,
+            self.function,
+            args.join(", ")
+        )?;
+        match (&self.result, &self.error) {
+            (Some(result), _) => write!(f, " -> {}", format_val(result))?,
+            (None, Some(error)) => write!(f, " -> {error}")?,
+            (None, None) => {},
+        }
+        if let (Some(cpu_insns), Some(mem_bytes)) = (self.cpu_insns, self.mem_bytes) {
+            write!(f, " [cpu: {cpu_insns}, mem: {mem_bytes}]")?;
+        }
+        writeln!(f)?;
+
+        let items = self.events.len() + self.calls.len();
+        let mut index = 0;
+        let mut prefixes = || {
+            index += 1;
+            match index == items {
+                true => (format!("{rest}└─ "), format!("{rest}   ")),
+                false => (format!("{rest}├─ "), format!("{rest}│  ")),
+            }
+        };
+
+        for event in &self.events {
+            let (first, _) = prefixes();
+            let ContractEventBody::V0(body) = &event.body;
+            let topics = body.topics.iter().map(format_val).collect::<Vec<_>>();
+            writeln!(
+                f,
+                "{first}event [{}] {}",
+                topics.join(", "),
+                format_val(&body.data)
+            )?;
+        }
+        for call in &self.calls {
+            let (first, rest) = prefixes();
+            call.write_tree(f, &first, &rest)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Short form of a value for the text trace, its readable JSON.
+fn format_val(value: &ScVal) -> String {
+    scval_to_json(value).to_string()
+}
@@ -1,26 +1,159 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
-use soroban_env_common::xdr::DiagnosticEvent;
-use soroban_env_host::{xdr::TransactionV1Envelope, LedgerInfo};
+use anyhow::{anyhow, Result};
+use im::{HashMap, OrdMap};
+use soroban_env_common::xdr::{DiagnosticEvent, ReadXdr};
+use soroban_env_host::{
+    xdr::{
+        BumpSequenceResult, Hash, InvokeHostFunctionResult, Limits, OperationBody, OperationResult,
+        OperationResultTr, ScVal, TransactionEnvelope, TransactionResult, TransactionResultResult,
+        TransactionV1Envelope, WriteXdr,
+    },
+    LedgerInfo,
+};
 
+use crate::utils::{failed_result, transaction_meta};
+
+#[derive(Clone)]
 pub struct TransactionInfo {
     pub envelope: TransactionV1Envelope,
-    pub result: Result<Vec<u8>, String>,
+    /// XDR of the returned value, `None` for operations that return nothing.
+    pub result: Result<Option<Vec<u8>>, String>,
     pub ledger_info: LedgerInfo,
     pub events: Vec<DiagnosticEvent>,
+    /// All the events recorded by the host while executing the transaction.
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+    /// Position of the transaction within its ledger, assigned by
+    /// [`TxStorage::insert`].
+    pub application_order: u32,
+}
+
+impl TransactionInfo {
+    pub fn envelope_xdr(&self) -> Result<String> {
+        Ok(TransactionEnvelope::Tx(self.envelope.clone()).to_xdr_base64(Limits::none())?)
+    }
+
+    pub fn result_xdr(&self, hash: &str) -> Result<String> {
+        let result = match &self.result {
+            Ok(_) => TransactionResultResult::TxSuccess(
+                vec![OperationResult::OpInner(self.operation_result(hash)?)].try_into()?,
+            ),
+            Err(_) => failed_result()?,
+        };
+
+        Ok(TransactionResult {
+            fee_charged: self.envelope.tx.fee as i64,
+            result,
+            ext: Default::default(),
+        }
+        .to_xdr_base64(Limits::none())?)
+    }
+
+    pub fn result_meta_xdr(&self) -> Result<String> {
+        let meta = match &self.result {
+            Ok(Some(result)) => transaction_meta(
+                self.events.iter().map(|e| e.event.clone()).collect(),
+                Some(ScVal::from_xdr(result, Limits::none())?),
+                self.diagnostic_events.clone(),
+            )?,
+            Ok(None) | Err(_) => transaction_meta(vec![], None, vec![])?,
+        };
+
+        Ok(meta.to_xdr_base64(Limits::none())?)
+    }
+
+    /// Base64 `DiagnosticEvent` XDR, `None` when nothing was recorded.
+    pub fn diagnostic_events_xdr(&self) -> Result<Option<Vec<String>>> {
+        if self.diagnostic_events.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            self.diagnostic_events
+                .iter()
+                .map(|event| event.to_xdr_base64(Limits::none()))
+                .collect::<Result<_, _>>()?,
+        ))
+    }
+
+    fn operation_result(&self, hash: &str) -> Result<OperationResultTr> {
+        Ok(
+            match self.envelope.tx.operations.first().map(|op| &op.body) {
+                Some(OperationBody::BumpSequence(_)) => {
+                    OperationResultTr::BumpSequence(BumpSequenceResult::Success)
+                },
+                _ => {
+                    OperationResultTr::InvokeHostFunction(InvokeHostFunctionResult::Success(Hash(
+                        hex::decode(hash)?
+                            .try_into()
+                            .map_err(|e| anyhow!("coudl not decode {e:?}"))?,
+                    )))
+                },
+            },
+        )
+    }
+
+    fn position(&self) -> (u32, u32) {
+        (self.ledger_info.sequence_number, self.application_order)
+    }
 }
 
-#[derive(Default)]
+/// Sent transactions, ordered by ledger and application order. Both maps are
+/// persistent, so clones share the transactions.
+#[derive(Clone, Default)]
 pub struct TxStorage {
-    storage: HashMap<String, TransactionInfo>,
+    storage: HashMap<String, Arc<TransactionInfo>>,
+    order: OrdMap<(u32, u32), String>,
 }
 
 impl TxStorage {
-    pub fn insert(&mut self, tx_hash: String, transaction_info: TransactionInfo) {
-        self.storage.insert(tx_hash, transaction_info);
+    pub fn insert(&mut self, tx_hash: String, mut transaction_info: TransactionInfo) {
+        if let Some(previous) = self.storage.remove(&tx_hash) {
+            self.order.remove(&previous.position());
+        }
+
+        let ledger = transaction_info.ledger_info.sequence_number;
+        transaction_info.application_order = self
+            .order
+            .range((ledger, 0)..=(ledger, u32::MAX))
+            .next_back()
+            .map_or(1, |((_, order), _)| order + 1);
+
+        self.order
+            .insert(transaction_info.position(), tx_hash.clone());
+        self.storage.insert(tx_hash, Arc::new(transaction_info));
     }
 
     pub fn get(&self, tx_hash: &str) -> Option<&TransactionInfo> {
-        self.storage.get(tx_hash)
+        self.storage.get(tx_hash).map(AsRef::as_ref)
+    }
+
+    /// Iterates over stored transactions in ledger and application order,
+    /// starting at the given position.
+    pub fn iter_from(
+        &self,
+        ledger: u32,
+        application_order: u32,
+    ) -> impl Iterator<Item = (&str, &TransactionInfo)> {
+        self.order
+            .range((ledger, application_order)..)
+            .filter_map(|(_, hash)| Some((hash.as_str(), self.get(hash)?)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TransactionInfo)> {
+        self.iter_from(0, 0)
+    }
+
+    /// Drops transactions from ledgers before `oldest_ledger`.
+    pub fn prune(&mut self, oldest_ledger: u32) {
+        let (pruned, first, mut retained) = self.order.split_lookup(&(oldest_ledger, 0));
+        if let Some(hash) = first {
+            retained.insert((oldest_ledger, 0), hash);
+        }
+
+        for hash in pruned.values() {
+            self.storage.remove(hash);
+        }
+        self.order = retained;
     }
 }
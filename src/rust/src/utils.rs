@@ -1,19 +1,22 @@
-use std::collections::HashSet;
+use std::{collections::BTreeSet, rc::Rc};
 
 use anyhow::Context;
 use sha2::{Digest, Sha256};
 use soroban_env_common::xdr::{
-    ContractCodeEntryExt, ContractCostType, Hash, InvokeHostFunctionResult, LedgerEntry,
-    LedgerEntryChangeType, LedgerEntryData, LedgerKey, Limits, OperationResult, OperationResultTr,
-    TransactionResultResult, TransactionSignaturePayload,
-    TransactionSignaturePayloadTaggedTransaction, TransactionV1Envelope, TtlEntry,
-};
-use soroban_env_host::{
-    budget::AsBudget, vm::VersionedContractCodeCostInputs, xdr::WriteXdr, LedgerInfo, ModuleCache,
+    ContractCodeEntry, ContractEvent, DiagnosticEvent, ExtensionPoint, Hash,
+    InvokeHostFunctionResult, LedgerEntry, LedgerFootprint, LedgerKey, Limits, OperationMeta,
+    OperationResult, OperationResultTr, ReadXdr, ScVal, SorobanTransactionMeta,
+    SorobanTransactionMetaExt, TransactionMeta, TransactionMetaV3, TransactionResultResult,
+    TransactionSignaturePayload, TransactionSignaturePayloadTaggedTransaction,
+    TransactionV1Envelope, TtlEntry,
 };
+use soroban_env_host::{e2e_invoke, storage::SnapshotSource, xdr::WriteXdr, LedgerInfo};
 use soroban_simulation::simulation::LedgerEntryDiff;
 
-use crate::{model::LedgerEntryChange, module_cache::new_module_cache};
+use crate::{
+    memory::Memory,
+    model::{FootprintDiagnostics, LedgerEntryChange, LedgerEntryChangeType},
+};
 
 pub fn tx_hash(
     envelope: &TransactionV1Envelope,
@@ -35,15 +38,10 @@ pub fn compute_key_hash(key: &LedgerKey) -> Vec<u8> {
     hash.to_vec()
 }
 
-pub fn sha256_hash_from_bytes_raw(bytes: &[u8], budget: impl AsBudget) -> anyhow::Result<[u8; 32]> {
-    budget
-        .as_budget()
-        .charge(
-            ContractCostType::ComputeSha256Hash,
-            Some(bytes.len() as u64),
-        )
-        .context("Failed to charge budget for SHA256 hash")?;
-    Ok(Sha256::digest(bytes).into())
+/// Whether the code is the one its hash identifies. Other code can only be
+/// written with the cheat codes, so it is never cached by hash.
+pub fn is_content_addressed(code: &ContractCodeEntry) -> bool {
+    <[u8; 32]>::from(Sha256::digest(code.code.as_slice())) == code.hash.0
 }
 
 pub fn ttl_entry(key: &LedgerKey, ttl: u32) -> TtlEntry {
@@ -53,66 +51,107 @@ pub fn ttl_entry(key: &LedgerKey, ttl: u32) -> TtlEntry {
     }
 }
 
-pub fn build_module_cache_for_entries(
-    ledger_info: &LedgerInfo,
-    ledger_entries_with_ttl: Vec<(LedgerEntry, Option<u32>)>,
-    restored_contracts: &HashSet<Hash>,
-) -> anyhow::Result<ModuleCache> {
-    let (cache, ctx) = new_module_cache().context("Failed to create new module cache")?;
-
-    for (e, _) in ledger_entries_with_ttl.iter() {
-        if let LedgerEntryData::ContractCode(cd) = &e.data {
-            let contract_id = Hash(sha256_hash_from_bytes_raw(&cd.code, ctx.as_budget())?);
-            if restored_contracts.contains(&contract_id) {
-                continue;
-            }
-            let code_cost_inputs = match &cd.ext {
-                ContractCodeEntryExt::V0 => VersionedContractCodeCostInputs::V0 {
-                    wasm_bytes: cd.code.len(),
-                },
-                ContractCodeEntryExt::V1(v1) => {
-                    VersionedContractCodeCostInputs::V1(v1.cost_inputs.clone())
-                },
+pub fn changes_from_simulation(
+    changes: Vec<LedgerEntryDiff>,
+) -> anyhow::Result<Vec<LedgerEntryChange>> {
+    changes
+        .into_iter()
+        .filter_map(|diff| {
+            let (change_type, entry) = match (&diff.state_before, &diff.state_after) {
+                (Some(_), Some(after)) => (LedgerEntryChangeType::Updated, after),
+                (Some(before), None) => (LedgerEntryChangeType::Deleted, before),
+                (None, Some(after)) => (LedgerEntryChangeType::Created, after),
+                (None, None) => return None,
             };
-            cache
-                .parse_and_cache_module(
-                    &ctx,
-                    ledger_info.protocol_version,
-                    &contract_id,
-                    &cd.code,
-                    code_cost_inputs,
-                )
-                .context("Failed to parse and cache module")?;
+
+            Some(
+                entry
+                    .to_key()
+                    .to_xdr_base64(Limits::none())
+                    .and_then(|key| {
+                        Ok(LedgerEntryChange {
+                            change_type,
+                            key,
+                            before: diff
+                                .state_before
+                                .map(|entry| entry.to_xdr_base64(Limits::none()))
+                                .transpose()?,
+                            after: diff
+                                .state_after
+                                .map(|entry| entry.to_xdr_base64(Limits::none()))
+                                .transpose()?,
+                        })
+                    }),
+            )
+        })
+        .collect::<Result<_, _>>()
+        .context("Failed to encode state changes")
+}
+
+/// Builds the state diff of a recording mode invocation against the state it
+/// was run on. Must be called before the changes are applied.
+pub fn changes_from_ledger_changes(
+    memory: &Memory,
+    changes: &[e2e_invoke::LedgerEntryChange],
+) -> anyhow::Result<Vec<LedgerEntryChange>> {
+    let mut diffs = vec![];
+
+    for change in changes.iter().filter(|change| !change.read_only) {
+        let key = LedgerKey::from_xdr(&change.encoded_key, Limits::none())?;
+        let state_before = memory
+            .get(&Rc::new(key))?
+            .map(|(entry, _)| (*entry).clone());
+        let state_after = change
+            .encoded_new_value
+            .as_ref()
+            .map(|encoded| LedgerEntry::from_xdr(encoded, Limits::none()))
+            .transpose()?;
+
+        if state_before != state_after {
+            diffs.push(LedgerEntryDiff {
+                state_before,
+                state_after,
+            });
         }
     }
-    Ok(cache)
+
+    changes_from_simulation(diffs)
 }
 
-pub fn changes_from_simulation(changes: Vec<LedgerEntryDiff>) -> Vec<LedgerEntryChange> {
-    changes
-        .into_iter()
-        .map(|diff| {
-            let change_type = match (diff.state_before.is_some(), diff.state_after.is_some()) {
-                (true, true) => LedgerEntryChangeType::Updated,
-                (true, false) => LedgerEntryChangeType::Removed,
-                (false, true) => LedgerEntryChangeType::Updated,
-                _ => panic!("unexpected"),
-            };
+/// Compares the footprint a transaction declared with the one recorded while
+/// running it.
+pub fn footprint_diagnostics(
+    declared: &LedgerFootprint,
+    recorded: &LedgerFootprint,
+) -> anyhow::Result<FootprintDiagnostics> {
+    let declared_keys: BTreeSet<_> = declared
+        .read_only
+        .iter()
+        .chain(declared.read_write.iter())
+        .collect();
+    let recorded_keys: BTreeSet<_> = recorded
+        .read_only
+        .iter()
+        .chain(recorded.read_write.iter())
+        .collect();
+    let declared_read_only: BTreeSet<_> = declared.read_only.iter().collect();
 
-            let key = match (&diff.state_after, &diff.state_before) {
-                (Some(entry), _) => entry.to_key(),
-                (_, Some(entry)) => entry.to_key(),
-                _ => panic!("unexpected"),
-            };
+    Ok(FootprintDiagnostics {
+        accessed_outside_footprint: encode_keys(recorded_keys.difference(&declared_keys).copied())?,
+        declared_not_accessed: encode_keys(declared_keys.difference(&recorded_keys).copied())?,
+        read_only_written: encode_keys(
+            recorded
+                .read_write
+                .iter()
+                .filter(|key| declared_read_only.contains(key)),
+        )?,
+    })
+}
 
-            LedgerEntryChange {
-                change_type,
-                key,
-                before: diff.state_before,
-                after: diff.state_after,
-            }
-        })
-        .collect()
+fn encode_keys<'a>(keys: impl Iterator<Item = &'a LedgerKey>) -> anyhow::Result<Vec<String>> {
+    Ok(keys
+        .map(|key| key.to_xdr_base64(Limits::none()))
+        .collect::<Result<_, _>>()?)
 }
 
 pub fn failed_result() -> anyhow::Result<TransactionResultResult> {
@@ -123,3 +162,32 @@ pub fn failed_result() -> anyhow::Result<TransactionResultResult> {
         .try_into()?,
     ))
 }
+
+/// Builds the transaction meta of a single invoke host function operation. The
+/// sandbox does not track per-transaction ledger changes, so only the Soroban
+/// part is filled in.
+pub fn transaction_meta(
+    events: Vec<ContractEvent>,
+    return_value: Option<ScVal>,
+    diagnostic_events: Vec<DiagnosticEvent>,
+) -> anyhow::Result<TransactionMeta> {
+    Ok(TransactionMeta::V3(TransactionMetaV3 {
+        ext: ExtensionPoint::V0,
+        tx_changes_before: Default::default(),
+        operations: vec![OperationMeta {
+            changes: Default::default(),
+        }]
+        .try_into()?,
+        tx_changes_after: Default::default(),
+        soroban_meta: return_value
+            .map(|return_value| -> anyhow::Result<_> {
+                Ok(SorobanTransactionMeta {
+                    ext: SorobanTransactionMetaExt::V0,
+                    events: events.try_into()?,
+                    return_value,
+                    diagnostic_events: diagnostic_events.try_into()?,
+                })
+            })
+            .transpose()?,
+    }))
+}
@@ -1,65 +1,152 @@
-use std::rc::Rc;
+use std::{collections::BTreeSet, fmt, rc::Rc, sync::Arc};
 
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, bail, Result};
 use ed25519_dalek::{Verifier, VerifyingKey};
 use soroban_env_common::xdr::{LedgerKey, LedgerKeyAccount, SignerKey, Uint256};
 use soroban_env_host::{
     xdr::{
-        AccountEntry, DecoratedSignature, Preconditions, PublicKey, SignatureHint,
-        TransactionV1Envelope,
+        AccountEntry, ContractEvent, ContractEventBody, ContractEventType, ContractEventV0,
+        DecoratedSignature, DiagnosticEvent, ExtensionPoint, Limits, OperationBody, Preconditions,
+        PublicKey, ScError, ScErrorCode, ScString, ScSymbol, ScVal, SignatureHint,
+        SorobanTransactionData, SorobanTransactionDataExt, TransactionEnvelope, TransactionExt,
+        TransactionResultResult, TransactionV1Envelope, WriteXdr,
     },
     LedgerInfo,
 };
 
-use crate::{memory::Memory, utils::tx_hash};
+use crate::{
+    memory::Memory,
+    network_config::{default_tx_limits, SorobanTxLimits},
+    utils::tx_hash,
+};
+
+/// Transaction rejected before being applied, with the result stellar-core
+/// would return for it.
+#[derive(Debug)]
+pub struct InvalidTransaction {
+    pub result: TransactionResultResult,
+    pub reason: String,
+}
+
+impl InvalidTransaction {
+    fn new(result: TransactionResultResult, reason: impl ToString) -> Self {
+        Self {
+            result,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn malformed(reason: impl ToString) -> Self {
+        Self::new(TransactionResultResult::TxMalformed, reason)
+    }
+
+    fn soroban_invalid(reason: impl ToString) -> Self {
+        Self::new(TransactionResultResult::TxSorobanInvalid, reason)
+    }
+
+    /// Diagnostic event carrying the reason, like the ones stellar-core emits
+    /// for rejected Soroban transactions.
+    pub fn diagnostic_event(&self) -> Result<DiagnosticEvent> {
+        Ok(DiagnosticEvent {
+            in_successful_contract_call: false,
+            event: ContractEvent {
+                ext: ExtensionPoint::V0,
+                contract_id: None,
+                type_: ContractEventType::Diagnostic,
+                body: ContractEventBody::V0(ContractEventV0 {
+                    topics: vec![
+                        ScVal::Symbol(ScSymbol("error".try_into()?)),
+                        ScVal::Error(ScError::Value(ScErrorCode::InvalidInput)),
+                    ]
+                    .try_into()?,
+                    data: ScVal::String(ScString(self.reason.as_str().try_into()?)),
+                }),
+            },
+        })
+    }
+}
+
+impl fmt::Display for InvalidTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.result.name(), self.reason)
+    }
+}
+
+impl std::error::Error for InvalidTransaction {}
 
 pub struct TxValidation {
-    memory: Rc<Memory>,
+    memory: Arc<Memory>,
 }
 
 impl TxValidation {
-    pub fn new(memory: Rc<Memory>) -> Self {
+    pub fn new(memory: Arc<Memory>) -> Self {
         Self { memory }
     }
 
+    /// Checks the source account can pay for the transaction and has signed
+    /// it, the way stellar-core does before applying it.
     pub fn validate(
         &self,
         envelope: &TransactionV1Envelope,
         ledger_info: &LedgerInfo,
-    ) -> Result<()> {
+        check_signatures: bool,
+    ) -> Result<(), InvalidTransaction> {
         let account_id = envelope.tx.source_account.clone().account_id();
         let key = LedgerKey::from(LedgerKeyAccount { account_id });
 
         let entry = self
             .memory
-            .get_account(Rc::new(key))?
-            .ok_or_else(|| anyhow!("account not found"))?;
+            .get_account(Rc::new(key))
+            .ok()
+            .flatten()
+            .ok_or_else(|| {
+                InvalidTransaction::new(TransactionResultResult::TxNoAccount, "account not found")
+            })?;
 
         if entry.seq_num.0 + 1 != envelope.tx.seq_num.0 {
-            bail!(
-                "sequence number mismatch, got {}, expected {}",
-                envelope.tx.seq_num.0,
-                entry.seq_num.0 + 1
-            );
+            return Err(InvalidTransaction::new(
+                TransactionResultResult::TxBadSeq,
+                format!(
+                    "sequence number mismatch, got {}, expected {}",
+                    envelope.tx.seq_num.0,
+                    entry.seq_num.0 + 1
+                ),
+            ));
         }
 
         if entry.balance < envelope.tx.fee as i64 {
-            bail!(
-                "insufficient balance: has {} needs {}",
-                entry.balance,
-                envelope.tx.fee
-            );
+            return Err(InvalidTransaction::new(
+                TransactionResultResult::TxInsufficientBalance,
+                format!(
+                    "insufficient balance: has {} needs {}",
+                    entry.balance, envelope.tx.fee
+                ),
+            ));
         }
 
         self.verify_time_conds(&envelope.tx.cond, ledger_info)?;
 
+        if !check_signatures {
+            return Ok(());
+        }
+
+        self.verify_signatures(envelope, &entry, ledger_info)
+            .map_err(|e| InvalidTransaction::new(TransactionResultResult::TxBadAuth, e))
+    }
+
+    fn verify_signatures(
+        &self,
+        envelope: &TransactionV1Envelope,
+        entry: &AccountEntry,
+        ledger_info: &LedgerInfo,
+    ) -> Result<()> {
         let hash = tx_hash(envelope, ledger_info)?;
 
         let mut weight = 0;
 
         for signature in envelope.signatures.iter() {
             let pk = self
-                .get_public_key(&entry, &signature.hint)
+                .get_public_key(entry, &signature.hint)
                 .ok_or(anyhow!("no matching signer found for signature hint"))?;
 
             self.verify_decorated_signature(&hash, signature, &pk)?;
@@ -74,17 +161,189 @@ impl TxValidation {
         Ok(())
     }
 
-    fn verify_time_conds(&self, conds: &Preconditions, ledger_info: &LedgerInfo) -> Result<()> {
+    /// Checks the Soroban part of the transaction against the network limits,
+    /// the same way stellar-core does before applying it.
+    pub fn validate_soroban(
+        &self,
+        envelope: &TransactionV1Envelope,
+    ) -> Result<(), InvalidTransaction> {
+        let operations = &envelope.tx.operations;
+        let has_soroban_op = operations.iter().any(|op| {
+            matches!(
+                op.body,
+                OperationBody::InvokeHostFunction(_)
+                    | OperationBody::ExtendFootprintTtl(_)
+                    | OperationBody::RestoreFootprint(_)
+            )
+        });
+
+        let data = match &envelope.tx.ext {
+            TransactionExt::V0 if !has_soroban_op => return Ok(()),
+            TransactionExt::V1(_) if !has_soroban_op => {
+                return Err(InvalidTransaction::malformed(
+                    "Soroban data set on a transaction without Soroban operations",
+                ))
+            },
+            _ if operations.len() != 1 => {
+                return Err(InvalidTransaction::malformed(
+                    "Soroban operation must be the only operation of the transaction",
+                ))
+            },
+            TransactionExt::V0 => {
+                return Err(InvalidTransaction::malformed(
+                    "Soroban transaction is missing Soroban data",
+                ))
+            },
+            TransactionExt::V1(data) => data,
+        };
+
+        if data.resource_fee < 0 || data.resource_fee > envelope.tx.fee as i64 {
+            return Err(InvalidTransaction::soroban_invalid(format!(
+                "resource fee {} is not within the transaction fee {}",
+                data.resource_fee, envelope.tx.fee
+            )));
+        }
+
+        let limits = default_tx_limits();
+
+        let size = TransactionEnvelope::Tx(envelope.clone())
+            .to_xdr(Limits::none())
+            .map_err(|e| InvalidTransaction::malformed(e.to_string()))?
+            .len();
+        if size > limits.tx_max_size_bytes as usize {
+            return Err(InvalidTransaction::soroban_invalid(format!(
+                "transaction size {size} exceeds the limit of {} bytes",
+                limits.tx_max_size_bytes
+            )));
+        }
+
+        self.validate_resources(data, &limits)
+    }
+
+    fn validate_resources(
+        &self,
+        data: &SorobanTransactionData,
+        limits: &SorobanTxLimits,
+    ) -> Result<(), InvalidTransaction> {
+        let resources = &data.resources;
+        let footprint = &resources.footprint;
+
+        let checks = [
+            (
+                "instructions",
+                resources.instructions,
+                limits.tx_max_instructions,
+            ),
+            (
+                "disk read bytes",
+                resources.disk_read_bytes,
+                limits.tx_max_disk_read_bytes,
+            ),
+            (
+                "write bytes",
+                resources.write_bytes,
+                limits.tx_max_write_bytes,
+            ),
+            (
+                "footprint entries",
+                (footprint.read_only.len() + footprint.read_write.len()) as u32,
+                limits.tx_max_footprint_entries,
+            ),
+            (
+                "written entries",
+                footprint.read_write.len() as u32,
+                limits.tx_max_write_ledger_entries,
+            ),
+        ];
+        for (resource, declared, limit) in checks {
+            if declared > limit {
+                return Err(InvalidTransaction::soroban_invalid(format!(
+                    "{resource} {declared} exceed the network limit of {limit}"
+                )));
+            }
+        }
+
+        let mut keys = BTreeSet::new();
+        for key in footprint
+            .read_only
+            .iter()
+            .chain(footprint.read_write.iter())
+        {
+            if !matches!(
+                key,
+                LedgerKey::Account(_)
+                    | LedgerKey::Trustline(_)
+                    | LedgerKey::ContractData(_)
+                    | LedgerKey::ContractCode(_)
+            ) {
+                return Err(InvalidTransaction::soroban_invalid(format!(
+                    "footprint contains a {} key",
+                    key.name()
+                )));
+            }
+
+            if !keys.insert(key) {
+                return Err(InvalidTransaction::soroban_invalid(format!(
+                    "footprint contains duplicate key {key:?}"
+                )));
+            }
+        }
+
+        let archived_entries = match &data.ext {
+            SorobanTransactionDataExt::V1(ext) => ext.archived_soroban_entries.as_slice(),
+            SorobanTransactionDataExt::V0 => &[],
+        };
+        if archived_entries
+            .iter()
+            .any(|index| *index as usize >= footprint.read_write.len())
+        {
+            return Err(InvalidTransaction::soroban_invalid(
+                "archived entry index is out of the read-write footprint",
+            ));
+        }
+
+        // Only classic entries and restored archived entries are read from
+        // disk, live Soroban state is kept in memory.
+        let disk_read_entries = keys
+            .iter()
+            .filter(|key| matches!(key, LedgerKey::Account(_) | LedgerKey::Trustline(_)))
+            .count()
+            + archived_entries.len();
+        if disk_read_entries > limits.tx_max_disk_read_entries as usize {
+            return Err(InvalidTransaction::soroban_invalid(format!(
+                "disk read entries {disk_read_entries} exceed the network limit of {}",
+                limits.tx_max_disk_read_entries
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn verify_time_conds(
+        &self,
+        conds: &Preconditions,
+        ledger_info: &LedgerInfo,
+    ) -> Result<(), InvalidTransaction> {
         match conds {
             Preconditions::None => return Ok(()),
             Preconditions::Time(time) => {
                 let now = ledger_info.timestamp;
-                ensure!(
-                    now <= time.max_time.0 && now >= time.min_time.0,
-                    "Current time {now} not within time bounds: [{}, {}]",
-                    time.min_time.0,
-                    time.max_time.0
-                );
+                // A zero max time leaves the bound open.
+                let result = if now < time.min_time.0 {
+                    TransactionResultResult::TxTooEarly
+                } else if time.max_time.0 != 0 && now > time.max_time.0 {
+                    TransactionResultResult::TxTooLate
+                } else {
+                    return Ok(());
+                };
+
+                return Err(InvalidTransaction::new(
+                    result,
+                    format!(
+                        "Current time {now} not within time bounds: [{}, {}]",
+                        time.min_time.0, time.max_time.0
+                    ),
+                ));
             },
             Preconditions::V2(v2) => {
                 eprintln!("not supported, will go through {v2:?}");
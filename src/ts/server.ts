@@ -9,7 +9,6 @@ import {
   rpc,
   Keypair,
 } from '@stellar/stellar-sdk';
-import { SorobanDataBuilder } from '@stellar/stellar-base';
 
 export class SandboxServer extends rpc.Server {
   constructor(private readonly sandbox: MarsRover) {
@@ -30,26 +29,49 @@ export class SandboxServer extends rpc.Server {
     return Promise.resolve(JSON.parse(this.sandbox.getNetworkInfo()));
   }
 
+  override getLatestLedger(): Promise<rpc.Api.GetLatestLedgerResponse> {
+    return Promise.resolve(JSON.parse(this.sandbox.getLatestLedger()));
+  }
+
+  override getLedgers(request: rpc.Api.GetLedgersRequest): Promise<rpc.Api.GetLedgersResponse> {
+    const response = JSON.parse(
+      this.sandbox.getLedgers(
+        request.startLedger,
+        request.pagination?.cursor,
+        request.pagination?.limit,
+      ),
+    );
+
+    response.ledgers = response.ledgers.map((ledger: any) => ({
+      ...ledger,
+      headerXdr: xdr.LedgerHeaderHistoryEntry.fromXDR(ledger.headerXdr, 'base64'),
+      metadataXdr: xdr.LedgerCloseMeta.fromXDR(ledger.metadataXdr, 'base64'),
+    }));
+
+    return Promise.resolve(response);
+  }
+
+  override getHealth(): Promise<rpc.Api.GetHealthResponse> {
+    return Promise.resolve(JSON.parse(this.sandbox.getHealth()));
+  }
+
+  override getVersionInfo(): Promise<rpc.Api.GetVersionInfoResponse> {
+    return Promise.resolve(JSON.parse(this.sandbox.getVersionInfo()));
+  }
+
   override async simulateTransaction(
     tx: Transaction | FeeBumpTransaction,
-    _addlResources?: rpc.Server.ResourceLeeway,
-    _authMode?: rpc.Api.SimulationAuthMode,
+    addlResources?: rpc.Server.ResourceLeeway,
+    authMode?: rpc.Api.SimulationAuthMode,
   ): Promise<rpc.Api.SimulateTransactionResponse> {
-    const simulation = JSON.parse(this.sandbox.simulateTx(tx.toEnvelope().toXDR('base64')));
-
-    if ('error' in simulation) {
-      return simulation;
-    }
-
-    simulation.transactionData = new SorobanDataBuilder(
-      xdr.SorobanTransactionData.fromXDR(simulation.transactionData, 'base64'),
+    const simulation: rpc.Api.RawSimulateTransactionResponse = JSON.parse(
+      await this.sandbox.simulateTxAsync(tx.toEnvelope().toXDR('base64'), {
+        authMode,
+        instructionLeeway: addlResources?.cpuInstructions,
+      }),
     );
-    simulation.result.auth = simulation.result.auth.map((authEntry: string) =>
-      xdr.SorobanAuthorizationEntry.fromXDR(authEntry, 'base64'),
-    );
-    simulation.result.retval = xdr.ScVal.fromXDR(simulation.result.retval, 'base64');
 
-    return simulation;
+    return rpc.parseRawSimulation(simulation);
   }
 
   override async getContractData(
@@ -81,11 +103,11 @@ export class SandboxServer extends rpc.Server {
     return await Promise.resolve(response);
   }
 
-  override sendTransaction(
+  override async sendTransaction(
     transaction: Transaction | FeeBumpTransaction,
   ): Promise<rpc.Api.SendTransactionResponse> {
-    return Promise.resolve(
-      JSON.parse(this.sandbox.sendTransaction(transaction.toEnvelope().toXDR('base64'))),
+    return JSON.parse(
+      await this.sandbox.sendTransactionAsync(transaction.toEnvelope().toXDR('base64')),
     );
   }
 
@@ -99,6 +121,54 @@ export class SandboxServer extends rpc.Server {
       response.returnValue = xdr.ScVal.fromXDR(Buffer.from(response.returnValue));
     }
 
+    if ('diagnosticEventsXdr' in response) {
+      response.diagnosticEventsXdr = response.diagnosticEventsXdr.map((e: string) =>
+        xdr.DiagnosticEvent.fromXDR(e, 'base64'),
+      );
+    }
+
+    return Promise.resolve(response);
+  }
+
+  override getTransactions(
+    request: rpc.Api.GetTransactionsRequest,
+  ): Promise<rpc.Api.GetTransactionsResponse> {
+    const response = JSON.parse(
+      this.sandbox.getTransactions(
+        request.startLedger,
+        request.pagination?.cursor,
+        request.pagination?.limit,
+      ),
+    );
+
+    response.transactions = response.transactions.map((tx: any) => {
+      const resultMetaXdr = xdr.TransactionMeta.fromXDR(tx.resultMetaXdr, 'base64');
+      const returnValue = resultMetaXdr.v3().sorobanMeta()?.returnValue();
+
+      return {
+        ...tx,
+        envelopeXdr: xdr.TransactionEnvelope.fromXDR(tx.envelopeXdr, 'base64'),
+        resultXdr: xdr.TransactionResult.fromXDR(tx.resultXdr, 'base64'),
+        resultMetaXdr,
+        ...(returnValue ? { returnValue } : {}),
+        ...(tx.diagnosticEventsXdr
+          ? {
+              diagnosticEventsXdr: tx.diagnosticEventsXdr.map((e: string) =>
+                xdr.DiagnosticEvent.fromXDR(e, 'base64'),
+              ),
+            }
+          : {}),
+        events: {
+          transactionEventsXdr: tx.events.transactionEventsXdr.map((e: string) =>
+            xdr.TransactionEvent.fromXDR(e, 'base64'),
+          ),
+          contractEventsXdr: tx.events.contractEventsXdr.map((op: string[]) =>
+            op.map((e) => xdr.ContractEvent.fromXDR(e, 'base64')),
+          ),
+        },
+      };
+    });
+
     return Promise.resolve(response);
   }
 }
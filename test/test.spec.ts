@@ -3,11 +3,17 @@ import {
   Contract,
   Keypair,
   Operation,
+  SorobanDataBuilder,
   TransactionBuilder,
+  authorizeEntry,
+  nativeToScVal,
+  rpc,
   xdr,
 } from '@stellar/stellar-sdk';
-import { readFileSync } from 'fs';
-import { getLedgerInfo, makeSandbox } from '../src/ts';
+import { mkdtempSync, readFileSync, writeFileSync } from 'fs';
+import { tmpdir } from 'os';
+import { join } from 'path';
+import { SandboxServer, SimulateTxOptions, getLedgerInfo, makeSandbox } from '../src/ts';
 
 describe('MarsRover Stellar Sandbox', () => {
   let sandbox: ReturnType<typeof makeSandbox>;
@@ -28,19 +34,50 @@ describe('MarsRover Stellar Sandbox', () => {
     return keypair;
   };
 
-  const buildTransaction = async (operation: xdr.Operation, sourceKeypair: Keypair) => {
+  // Transaction of the next sequence number, as built before simulation
+  const unpreparedTransaction = async (
+    operation: xdr.Operation,
+    sourceKeypair: Keypair,
+    sorobanData?: xdr.SorobanTransactionData,
+  ) => {
     const account = await server.getAccount(sourceKeypair.publicKey());
     const networkInfo = await server.getNetwork();
 
-    const transaction = new TransactionBuilder(account, {
+    const builder = new TransactionBuilder(account, {
       fee: '1000000',
       networkPassphrase: networkInfo.passphrase,
-    })
-      .addOperation(operation)
-      .setTimeout(30)
-      .build();
+    }).addOperation(operation);
+    if (sorobanData) {
+      builder.setSorobanData(sorobanData);
+    }
 
-    return await server.prepareTransaction(transaction);
+    return builder.setTimeout(30).build();
+  };
+
+  const buildTransaction = async (operation: xdr.Operation, sourceKeypair: Keypair) =>
+    await server.prepareTransaction(await unpreparedTransaction(operation, sourceKeypair));
+
+  // Transaction of an envelope edited after being built
+  const fromEnvelope = async (envelope: xdr.TransactionEnvelope) =>
+    TransactionBuilder.fromXDR(envelope, (await server.getNetwork()).passphrase);
+
+  const deployAdapter = (owner: Keypair, { init = false }: { init?: boolean } = {}) => {
+    const ownerAddress = new Address(owner.publicKey()).toScAddress();
+    const wasmHash = marsRover.uploadWasm(readFileSync('./test/redstone_adapter.wasm'));
+    const contractAddress = marsRover.deployContract(wasmHash, ownerAddress.toXDR('base64'));
+    if (init) {
+      marsRover.invoke(
+        contractAddress,
+        'init',
+        [xdr.ScVal.scvAddress(ownerAddress).toXDR('base64')],
+        { authMode: 'mock_all', commit: true },
+      );
+    }
+    const contract = new Contract(
+      Address.fromScAddress(xdr.ScAddress.fromXDR(contractAddress, 'base64')).toString(),
+    );
+
+    return { wasmHash, contractAddress, contract };
   };
 
   const executeTransaction = async (transaction: any, signerKeypair: Keypair) => {
@@ -61,14 +98,13 @@ describe('MarsRover Stellar Sandbox', () => {
       const invalidTxXdr =
         'AAAAAgAAAADMhyUr2DTDvFw70TSRmUhm52A7PuMt8uIOjFhC0uBuQAADJYEABOVfAAAABAAAAAEAAAAAAAAAAAAAAABo0rExAAAAAAAAAAEAAAAAAAAAGAAAAAAAAAABq4P5a+MLZ/WiVyampwIfs6crA21Ih8/p1VIFkMe4clcAAAAMY2hhbmdlX293bmVyAAAAAQAAABIAAAAAAAAAAPqS9Q/j4wXhAhrzZpNIu33tjelksUUC2T/fWnuxWO1pAAAAAQAAAAEAAAAAAAAAAPqS9Q/j4wXhAhrzZpNIu33tjelksUUC2T/fWnuxWO1pBztbQQm6H94AAAAAAAAAAQAAAAAAAAABq4P5a+MLZ/WiVyampwIfs6crA21Ih8/p1VIFkMe4clcAAAAMY2hhbmdlX293bmVyAAAAAQAAABIAAAAAAAAAAPqS9Q/j4wXhAhrzZpNIu33tjelksUUC2T/fWnuxWO1pAAAAAAAAAAEAAAAAAAAAAgAAAAAAAAAA+pL1D+PjBeECGvNmk0i7fe2N6WSxRQLZP99ae7FY7WkAAAAHDOxN+5wG3QW5dPtODYSdkZ7trvqVPuHZRWiNsaFO32EAAAACAAAABgAAAAAAAAAA+pL1D+PjBeECGvNmk0i7fe2N6WSxRQLZP99ae7FY7WkAAAAVBztbQQm6H94AAAAAAAAABgAAAAGrg/lr4wtn9aJXJqanAh+zpysDbUiHz+nVUgWQx7hyVwAAABQAAAABABH2gwAAAJAAAAEcAAAAAAADJR0AAAAA';
 
-      await expect(async () => {
-        await server.sendTransaction(
-          TransactionBuilder.fromXDR(
-            invalidTxXdr,
-            await server.getNetwork().then((n) => n.passphrase),
-          ),
-        );
-      }).rejects.toThrow();
+      const response = await server.sendTransaction(
+        TransactionBuilder.fromXDR(
+          invalidTxXdr,
+          await server.getNetwork().then((n) => n.passphrase),
+        ),
+      );
+      expect(response.status).toBe('ERROR');
     });
 
     it('should return network information', async () => {
@@ -102,6 +138,153 @@ describe('MarsRover Stellar Sandbox', () => {
       expect(account.accountId()).toBe(keypair.publicKey());
       expect(account.sequenceNumber()).toBe('0');
     });
+
+    it('should fork the sandbox', async () => {
+      const keypair = createFundedAccount(1000);
+      const accountKey = keypair.xdrPublicKey().toXDR('base64');
+      const fork = marsRover.fork();
+      const forkServer = new SandboxServer(fork);
+
+      fork.fundAccount(accountKey, 5);
+      fork.setSequence(getLedgerInfo(marsRover).sequence_number + 1);
+
+      expect(Number(fork.getBalance(accountKey))).toBe(5);
+      expect(Number(marsRover.getBalance(accountKey))).toBe(1000);
+      expect((await forkServer.getAccount(keypair.publicKey())).accountId()).toBe(
+        keypair.publicKey(),
+      );
+      expect(getLedgerInfo(fork).sequence_number).toBe(
+        getLedgerInfo(marsRover).sequence_number + 1,
+      );
+    });
+  });
+
+  describe('Transaction Submission', () => {
+    const buildBumpSequence = (sourceKeypair: Keypair, bumpTo: string) =>
+      unpreparedTransaction(Operation.bumpSequence({ bumpTo }), sourceKeypair);
+
+    it('should bump the sequence number', async () => {
+      const keypair = createFundedAccount();
+      const transaction = await buildBumpSequence(keypair, '1000');
+      transaction.sign(keypair);
+
+      const response = await server.sendTransaction(transaction);
+      expect(response.status).toBe('PENDING');
+
+      const account = await server.getAccount(keypair.publicKey());
+      expect(account.sequenceNumber()).toBe('1000');
+    });
+
+    it('should report resubmitted transactions as duplicates', async () => {
+      const keypair = createFundedAccount();
+      const transaction = await buildBumpSequence(keypair, '0');
+      transaction.sign(keypair);
+
+      await server.sendTransaction(transaction);
+      const sequence = (await server.getAccount(keypair.publicKey())).sequenceNumber();
+
+      const response = await server.sendTransaction(transaction);
+      expect(response.status).toBe('DUPLICATE');
+      expect((await server.getAccount(keypair.publicKey())).sequenceNumber()).toBe(sequence);
+    });
+
+    it('should not consume the sequence number of invalid transactions', async () => {
+      const keypair = createFundedAccount();
+      const sequence = (await server.getAccount(keypair.publicKey())).sequenceNumber();
+      const unsignedTx = await buildBumpSequence(keypair, '0');
+
+      const response = await server.sendTransaction(unsignedTx);
+      expect(response.status).toBe('ERROR');
+      expect((await server.getAccount(keypair.publicKey())).sequenceNumber()).toBe(sequence);
+    });
+
+    it('should reject Soroban transactions with a resource fee above the fee', async () => {
+      const keypair = createFundedAccount();
+      const sequence = (await server.getAccount(keypair.publicKey())).sequenceNumber();
+
+      const prepared = await buildTransaction(
+        Operation.uploadContractWasm({ wasm: readFileSync('./test/redstone_adapter.wasm') }),
+        keypair,
+      );
+      const envelope = prepared.toEnvelope();
+      envelope.v1().tx().ext().sorobanData().resourceFee(xdr.Int64.fromString('10000000000'));
+
+      const transaction = await fromEnvelope(envelope);
+      transaction.sign(keypair);
+
+      const response = await server.sendTransaction(transaction);
+      expect(response.status).toBe('ERROR');
+      expect((await server.getAccount(keypair.publicKey())).sequenceNumber()).toBe(sequence);
+    });
+
+    it('should report keys accessed outside of the footprint', async () => {
+      const keypair = createFundedAccount();
+
+      const prepared = await buildTransaction(
+        Operation.uploadContractWasm({ wasm: readFileSync('./test/redstone_adapter.wasm') }),
+        keypair,
+      );
+      const envelope = prepared.toEnvelope();
+      const footprint = envelope.v1().tx().ext().sorobanData().resources().footprint();
+      const [codeKey] = footprint.readWrite();
+      footprint.readWrite([]);
+
+      const transaction = await fromEnvelope(envelope);
+      transaction.sign(keypair);
+
+      const response: any = await server.sendTransaction(transaction);
+      expect(response.status).toBe('ERROR');
+      expect(response.footprintDiagnostics.accessedOutsideFootprint).toEqual([
+        codeKey.toXDR('base64'),
+      ]);
+    });
+  });
+
+  describe('Ledger History', () => {
+    it('should report the latest ledger', async () => {
+      const info = getLedgerInfo(marsRover);
+      const latest = await server.getLatestLedger();
+
+      expect(latest.sequence).toBe(info.sequence_number);
+      expect(latest.id).toHaveLength(64);
+    });
+
+    it('should keep a header for every closed ledger', async () => {
+      const start = getLedgerInfo(marsRover).sequence_number;
+      marsRover.setSequence(start + 1);
+      marsRover.setSequence(start + 2);
+
+      const health = await server.getHealth();
+      expect(health.oldestLedger).toBe(start);
+      expect(health.latestLedger).toBe(start + 2);
+
+      const firstPage = await server.getLedgers({ startLedger: start, pagination: { limit: 2 } });
+      expect(firstPage.ledgers.map((l) => l.sequence)).toEqual([start, start + 1]);
+      expect(firstPage.ledgers[0].headerXdr.hash().toString('hex')).toBe(firstPage.ledgers[0].hash);
+
+      const secondPage = await server.getLedgers({ pagination: { cursor: firstPage.cursor } });
+      expect(secondPage.ledgers.map((l) => l.sequence)).toEqual([start + 2]);
+      expect(secondPage.ledgers[0].headerXdr.header().previousLedgerHash().toString('hex')).toBe(
+        firstPage.ledgers[1].hash,
+      );
+    });
+
+    it('should drop ledgers outside of the retention window', async () => {
+      const start = getLedgerInfo(marsRover).sequence_number;
+      marsRover.setSequence(start + 1);
+      marsRover.setSequence(start + 2);
+      marsRover.setRetentionWindow(2);
+
+      const health = await server.getHealth();
+      expect(health.oldestLedger).toBe(start + 1);
+      expect(health.ledgerRetentionWindow).toBe(2);
+    });
+
+    it('should return version info', async () => {
+      const versionInfo = await server.getVersionInfo();
+
+      expect(versionInfo.protocolVersion).toBe(getLedgerInfo(marsRover).protocol_version);
+    });
   });
 
   describe('Contract Operations', () => {
@@ -111,6 +294,44 @@ describe('MarsRover Stellar Sandbox', () => {
       contractWasm = readFileSync('./test/redstone_adapter.wasm');
     });
 
+    it('should list sent transactions in application order', async () => {
+      const ownerKeypair = createFundedAccount();
+      const start = getLedgerInfo(marsRover).sequence_number;
+
+      const uploadTx = await buildTransaction(
+        Operation.uploadContractWasm({ wasm: contractWasm }),
+        ownerKeypair,
+      );
+      const wasmHash = (await executeTransaction(uploadTx, ownerKeypair)).bytes();
+
+      marsRover.setSequence(start + 1);
+
+      const createContractTx = await buildTransaction(
+        Operation.createCustomContract({
+          wasmHash,
+          address: Address.fromString(ownerKeypair.publicKey()),
+        }),
+        ownerKeypair,
+      );
+      await executeTransaction(createContractTx, ownerKeypair);
+
+      const firstPage = await server.getTransactions({
+        startLedger: start,
+        pagination: { limit: 1 },
+      });
+      expect(firstPage.transactions).toHaveLength(1);
+      expect(firstPage.transactions[0].ledger).toBe(start);
+      expect(firstPage.transactions[0].applicationOrder).toBe(1);
+      expect(firstPage.transactions[0].returnValue?.bytes()).toEqual(wasmHash);
+
+      const secondPage = await server.getTransactions({
+        pagination: { cursor: firstPage.cursor, limit: 10 },
+      });
+      expect(secondPage.transactions).toHaveLength(1);
+      expect(secondPage.transactions[0].ledger).toBe(start + 1);
+      expect(secondPage.transactions[0].status).toBe('SUCCESS');
+    });
+
     it('should deploy contract and execute operations', async () => {
       const ownerKeypair = createFundedAccount();
       const userKeypair = createFundedAccount();
@@ -256,6 +477,122 @@ describe('MarsRover Stellar Sandbox', () => {
     });
   });
 
+  describe('Cheat Codes', () => {
+    const contractAddress = Address.contract(Buffer.alloc(32, 1));
+    const key = xdr.ScVal.scvString('key');
+
+    const setData = (val: xdr.ScVal, liveUntil?: number) =>
+      marsRover.setContractData(
+        contractAddress.toScAddress().toXDR('base64'),
+        key.toXDR('base64'),
+        val.toXDR('base64'),
+        'persistent',
+        liveUntil,
+      );
+
+    const dataKey = () =>
+      xdr.LedgerKey.contractData(
+        new xdr.LedgerKeyContractData({
+          contract: contractAddress.toScAddress(),
+          key,
+          durability: xdr.ContractDataDurability.persistent(),
+        }),
+      ).toXDR('base64');
+
+    it('should write contract data directly', async () => {
+      setData(xdr.ScVal.scvU32(42), 1000);
+
+      const entry = await server.getContractData(contractAddress, key);
+
+      expect(entry.val.contractData().val().u32()).toBe(42);
+      expect(entry.liveUntilLedgerSeq).toBe(1000);
+    });
+
+    it('should upload and deploy contract directly', async () => {
+      const owner = createFundedAccount();
+      const { wasmHash, contract } = deployAdapter(owner);
+
+      expect(wasmHash).toMatch(/^[0-9a-f]{64}$/);
+
+      const initTx = await buildTransaction(
+        contract.call('init', xdr.ScVal.scvAddress(new Address(owner.publicKey()).toScAddress())),
+        owner,
+      );
+
+      await executeTransaction(initTx, owner);
+    });
+
+    it('should invoke contract directly', () => {
+      const owner = createFundedAccount();
+      const { contractAddress } = deployAdapter(owner);
+      const ownerArg = xdr.ScVal.scvAddress(new Address(owner.publicKey()).toScAddress());
+
+      const dryRun = JSON.parse(
+        marsRover.invoke(contractAddress, 'init', [ownerArg.toXDR('base64')]),
+      );
+      expect(dryRun.stateChanges.length).toBeGreaterThan(0);
+
+      const unauthorized = JSON.parse(
+        marsRover.invoke(contractAddress, 'change_owner', [ownerArg.toXDR('base64')], {
+          authMode: 'enforcing',
+        }),
+      );
+      expect(unauthorized.error).toBeDefined();
+
+      const threshold = JSON.parse(marsRover.invoke(contractAddress, 'unique_signer_threshold', []));
+      expect(xdr.ScVal.fromXDR(threshold.retval, 'base64').u64().toString()).toBe('3');
+    });
+
+    it('should run async variants on a worker thread', async () => {
+      const owner = createFundedAccount();
+      const ownerAddress = new Address(owner.publicKey()).toScAddress();
+      const wasmHash = await marsRover.uploadWasmAsync(
+        readFileSync('./test/redstone_adapter.wasm'),
+      );
+      const contractAddress = await marsRover.deployContractAsync(
+        wasmHash,
+        ownerAddress.toXDR('base64'),
+      );
+      const contract = new Contract(
+        Address.fromScAddress(xdr.ScAddress.fromXDR(contractAddress, 'base64')).toString(),
+      );
+
+      const pending = marsRover.invokeAsync(contractAddress, 'unique_signer_threshold', []);
+      expect(pending).toBeInstanceOf(Promise);
+      const threshold = JSON.parse(await pending);
+      expect(xdr.ScVal.fromXDR(threshold.retval, 'base64').u64().toString()).toBe('3');
+
+      const initTx = await buildTransaction(
+        contract.call('init', xdr.ScVal.scvAddress(ownerAddress)),
+        owner,
+      );
+      initTx.sign(owner);
+      const simulations = await Promise.all(
+        [1, 2, 3].map(() => marsRover.simulateTxAsync(initTx.toEnvelope().toXDR('base64'))),
+      );
+      expect(new Set(simulations).size).toBe(1);
+
+      const response = JSON.parse(
+        await marsRover.sendTransactionAsync(initTx.toEnvelope().toXDR('base64')),
+      );
+      expect(response.status).toBe('PENDING');
+
+      await expect(marsRover.simulateTxAsync('not xdr')).rejects.toThrow();
+    });
+
+    it('should update ttl and delete entries', async () => {
+      setData(xdr.ScVal.scvU32(42));
+      marsRover.setTtl(dataKey(), 5000);
+
+      const entry = await server.getContractData(contractAddress, key);
+      expect(entry.liveUntilLedgerSeq).toBe(5000);
+
+      marsRover.deleteLedgerEntry(dataKey());
+
+      await expect(server.getContractData(contractAddress, key)).rejects.toThrow();
+    });
+  });
+
   describe('Error Handling', () => {
     it('should handle invalid account keys', () => {
       expect(() => {
@@ -334,5 +671,437 @@ describe('MarsRover Stellar Sandbox', () => {
         await executeTransaction(unauthorizedTx, unauthorizedKeypair);
       }).rejects.toThrow();
     });
+
+    it('should accept unsigned authorizations when mocking all auths', async () => {
+      const ownerKeypair = createFundedAccount();
+      const userKeypair = createFundedAccount();
+
+      const { contract } = deployAdapter(ownerKeypair, { init: true });
+      const ownerArg = xdr.ScVal.scvAddress(new Address(ownerKeypair.publicKey()).toScAddress());
+
+      const changeOwnerTx = await buildTransaction(
+        contract.call(
+          'change_owner',
+          xdr.ScVal.scvAddress(new Address(userKeypair.publicKey()).toScAddress()),
+        ),
+        userKeypair,
+      );
+      changeOwnerTx.sign(userKeypair);
+
+      const response = JSON.parse(
+        marsRover.sendTransaction(changeOwnerTx.toEnvelope().toXDR('base64'), true),
+      );
+
+      expect(response.status).toBe('PENDING');
+      expect(response.requiredAuth).toHaveLength(1);
+
+      // only the authorizations are mocked, the footprint is still enforced
+      const unpreparedTx = await unpreparedTransaction(
+        contract.call('change_owner', ownerArg),
+        userKeypair,
+        new SorobanDataBuilder().build(),
+      );
+      unpreparedTx.sign(userKeypair);
+
+      const rejected = JSON.parse(
+        marsRover.sendTransaction(unpreparedTx.toEnvelope().toXDR('base64'), true),
+      );
+
+      expect(rejected.status).toBe('ERROR');
+      expect(rejected.footprintDiagnostics.accessedOutsideFootprint.length).toBeGreaterThan(0);
+    });
+
+    it('should skip signatures for impersonated accounts', async () => {
+      const adminKeypair = createFundedAccount();
+      const userKeypair = createFundedAccount();
+      const admin = new Address(adminKeypair.publicKey()).toScAddress();
+
+      const { contract } = deployAdapter(adminKeypair, { init: true });
+
+      marsRover.impersonate(admin.toXDR('base64'));
+
+      const changeOwnerTx = await buildTransaction(
+        contract.call(
+          'change_owner',
+          xdr.ScVal.scvAddress(new Address(userKeypair.publicKey()).toScAddress()),
+        ),
+        adminKeypair,
+      );
+      const response = await server.sendTransaction(changeOwnerTx);
+
+      expect(response.status).toBe('PENDING');
+
+      marsRover.stopImpersonating(admin.toXDR('base64'));
+
+      const unsignedTx = await buildTransaction(
+        contract.call('change_owner', xdr.ScVal.scvAddress(admin)),
+        adminKeypair,
+      );
+
+      const rejected = await server.sendTransaction(unsignedTx);
+      expect(rejected.status).toBe('ERROR');
+    });
+
+    it('should verify the authorizations of addresses that are not impersonated', async () => {
+      const ownerKeypair = createFundedAccount();
+      const userKeypair = createFundedAccount();
+
+      const { contract } = deployAdapter(ownerKeypair, { init: true });
+
+      marsRover.impersonate(new Address(Keypair.random().publicKey()).toScAddress().toXDR('base64'));
+
+      const networkInfo = await server.getNetwork();
+      const validUntil = getLedgerInfo(marsRover).sequence_number + 100;
+      const changeOwner = async (authSigner?: Keypair) => {
+        const prepared = await buildTransaction(
+          contract.call(
+            'change_owner',
+            xdr.ScVal.scvAddress(new Address(userKeypair.publicKey()).toScAddress()),
+          ),
+          userKeypair,
+        );
+        const envelope = prepared.toEnvelope();
+        const op = envelope.v1().tx().operations()[0].body().invokeHostFunctionOp();
+        if (authSigner) {
+          op.auth(
+            await Promise.all(
+              op
+                .auth()
+                .map((entry) =>
+                  authorizeEntry(entry, authSigner, validUntil, networkInfo.passphrase),
+                ),
+            ),
+          );
+        }
+        const transaction = await fromEnvelope(envelope);
+        transaction.sign(userKeypair);
+
+        return JSON.parse(marsRover.sendTransaction(transaction.toEnvelope().toXDR('base64')))
+          .status;
+      };
+
+      expect(await changeOwner()).toBe('ERROR');
+      expect(await changeOwner(Keypair.random())).toBe('ERROR');
+      expect(await changeOwner(ownerKeypair)).toBe('PENDING');
+    });
+  });
+
+  describe('Simulation', () => {
+    it('should return simulations in the stellar-rpc format', async () => {
+      const keypair = createFundedAccount();
+
+      const uploadTx = await unpreparedTransaction(
+        Operation.uploadContractWasm({ wasm: readFileSync('./test/redstone_adapter.wasm') }),
+        keypair,
+      );
+
+      const simulation = JSON.parse(marsRover.simulateTx(uploadTx.toEnvelope().toXDR('base64')));
+
+      expect(Number(simulation.cost.cpuInsns)).toBeGreaterThan(0);
+      expect(Number(simulation.cost.memBytes)).toBeGreaterThan(0);
+      expect(simulation.results).toHaveLength(1);
+      expect(xdr.ScVal.fromXDR(simulation.results[0].xdr, 'base64').bytes()).toHaveLength(32);
+      expect(simulation.stateChanges).toHaveLength(1);
+      expect(simulation.stateChanges[0].type).toBe('created');
+      expect(simulation.stateChanges[0].before).toBeNull();
+      expect(xdr.LedgerKey.fromXDR(simulation.stateChanges[0].key, 'base64').switch()).toBe(
+        xdr.LedgerEntryType.contractCode(),
+      );
+    });
+
+    it('should honor the simulation auth mode and instruction leeway', async () => {
+      const adminKeypair = createFundedAccount();
+      const admin = new Address(adminKeypair.publicKey()).toScAddress();
+
+      const { contract } = deployAdapter(adminKeypair, { init: true });
+
+      const changeOwnerTx = await unpreparedTransaction(
+        contract.call('change_owner', xdr.ScVal.scvAddress(admin)),
+        adminKeypair,
+      );
+
+      const enforced = await server.simulateTransaction(changeOwnerTx, undefined, 'enforce');
+      expect(rpc.Api.isSimulationError(enforced)).toBe(true);
+
+      const recorded = await server.simulateTransaction(changeOwnerTx);
+      const withLeeway = await server.simulateTransaction(changeOwnerTx, {
+        cpuInstructions: 1_000_000,
+      });
+      if (!rpc.Api.isSimulationSuccess(recorded) || !rpc.Api.isSimulationSuccess(withLeeway)) {
+        throw new Error('simulation failed');
+      }
+
+      expect(recorded.result?.auth).toHaveLength(1);
+      expect(withLeeway.transactionData.build().resources().instructions()).toBeGreaterThan(
+        recorded.transactionData.build().resources().instructions(),
+      );
+    });
+
+    it('should simulate against snapshots, history and overridden state', async () => {
+      const adminKeypair = createFundedAccount();
+      const userKeypair = createFundedAccount();
+      const admin = new Address(adminKeypair.publicKey()).toScAddress();
+      const user = new Address(userKeypair.publicKey()).toScAddress();
+
+      const { contractAddress, contract } = deployAdapter(adminKeypair, { init: true });
+
+      const start = getLedgerInfo(marsRover).sequence_number;
+      const snapshotId = marsRover.snapshot();
+
+      marsRover.setSequence(start + 1);
+      marsRover.invoke(
+        contractAddress,
+        'change_owner',
+        [xdr.ScVal.scvAddress(user).toXDR('base64')],
+        { authMode: 'mock_all', commit: true },
+      );
+
+      const changeOwnerTx = await unpreparedTransaction(
+        contract.call('change_owner', xdr.ScVal.scvAddress(admin)),
+        adminKeypair,
+      );
+      const envelope = changeOwnerTx.toEnvelope().toXDR('base64');
+
+      // the owner authorizing the call is the transaction source only while admin owns the contract
+      const requiredCredentials = (options?: SimulateTxOptions) => {
+        const simulation = JSON.parse(marsRover.simulateTx(envelope, options));
+
+        return xdr.SorobanAuthorizationEntry.fromXDR(simulation.results[0].auth[0], 'base64')
+          .credentials()
+          .switch();
+      };
+
+      expect(requiredCredentials()).toBe(xdr.SorobanCredentialsType.sorobanCredentialsAddress());
+      expect(requiredCredentials({ snapshotId })).toBe(
+        xdr.SorobanCredentialsType.sorobanCredentialsSourceAccount(),
+      );
+      expect(requiredCredentials({ ledger: start })).toBe(
+        xdr.SorobanCredentialsType.sorobanCredentialsSourceAccount(),
+      );
+
+      const instanceKey = xdr.LedgerKey.contractData(
+        new xdr.LedgerKeyContractData({
+          contract: xdr.ScAddress.fromXDR(contractAddress, 'base64'),
+          key: xdr.ScVal.scvLedgerKeyContractInstance(),
+          durability: xdr.ContractDataDurability.persistent(),
+        }),
+      );
+      const withoutInstance = JSON.parse(
+        marsRover.simulateTx(envelope, { removedKeys: [instanceKey.toXDR('base64')] }),
+      );
+      expect(withoutInstance.error).toBeDefined();
+      expect(requiredCredentials()).toBe(xdr.SorobanCredentialsType.sorobanCredentialsAddress());
+    });
+
+    it('should simulate a batch of transactions in order', async () => {
+      const owner = createFundedAccount();
+      const { contract } = deployAdapter(owner, { init: true });
+
+      const envelopes = await Promise.all(
+        [1, 2, 3, 4].map(async () => {
+          const newOwner = new Address(Keypair.random().publicKey()).toScAddress();
+          const transaction = await unpreparedTransaction(
+            contract.call('change_owner', xdr.ScVal.scvAddress(newOwner)),
+            owner,
+          );
+
+          return transaction.toEnvelope().toXDR('base64');
+        }),
+      );
+
+      const batch = JSON.parse(marsRover.simulateBatch(envelopes));
+      expect(batch).toEqual(
+        envelopes.map((envelope) => JSON.parse(marsRover.simulateTx(envelope))),
+      );
+      expect(JSON.parse(await marsRover.simulateBatchAsync(envelopes))).toEqual(batch);
+      expect(() => marsRover.simulateBatch([...envelopes, 'not xdr'])).toThrow();
+    });
+  });
+
+  describe('Diagnostics', () => {
+    it('should trace contract calls', async () => {
+      const adminKeypair = createFundedAccount();
+      const admin = new Address(adminKeypair.publicKey()).toScAddress();
+
+      const { contract } = deployAdapter(adminKeypair);
+
+      const initTx = await buildTransaction(
+        contract.call('init', xdr.ScVal.scvAddress(admin)),
+        adminKeypair,
+      );
+      initTx.sign(adminKeypair);
+      const response: any = await server.sendTransaction(initTx);
+
+      const [init] = response.trace.calls;
+      expect(init.contract).toBe(contract.contractId());
+      expect(init.function).toBe('init');
+      expect(init.argNames).toEqual(['owner']);
+      expect(init.cpuInsns).toBeGreaterThan(0);
+      expect(response.trace.text).toContain(`${contract.contractId()}.init(`);
+
+      const readTx = await unpreparedTransaction(
+        contract.call('read_prices', xdr.ScVal.scvVec([xdr.ScVal.scvSymbol('ETH')])),
+        adminKeypair,
+      );
+
+      const simulation = JSON.parse(marsRover.simulateTx(readTx.toEnvelope().toXDR('base64')));
+      const [read] = simulation.trace.calls;
+      expect(read.function).toBe('read_prices');
+      expect(read.result).toBeUndefined();
+      expect(read.error).toContain('UnreachableCodeReached');
+      expect(read.cpuInsns).toBeGreaterThan(0);
+    });
+
+    it('should profile the budget of sent and simulated transactions', async () => {
+      const adminKeypair = createFundedAccount();
+      const admin = new Address(adminKeypair.publicKey()).toScAddress();
+
+      const { contract } = deployAdapter(adminKeypair);
+
+      const initTx = await buildTransaction(
+        contract.call('init', xdr.ScVal.scvAddress(admin)),
+        adminKeypair,
+      );
+      const simulation = JSON.parse(marsRover.simulateTx(initTx.toEnvelope().toXDR('base64')));
+
+      initTx.sign(adminKeypair);
+      const { profile }: any = await server.sendTransaction(initTx);
+
+      const costTypes = profile.costTypes.map((usage: any) => usage.costType);
+      expect(costTypes).toContain('WasmInsnExec');
+      expect(costTypes).toContain('VmCachedInstantiation');
+      expect(profile.writeEntries).toBe(1);
+      expect(profile.writeBytes).toBeGreaterThan(0);
+      expect(simulation.profile.writeEntries).toBe(profile.writeEntries);
+      expect(simulation.profile.cpuInsns).toBeGreaterThan(0);
+    });
+
+    it('should store the diagnostic events of sent transactions', async () => {
+      const adminKeypair = createFundedAccount();
+      const admin = new Address(adminKeypair.publicKey()).toScAddress();
+
+      const { contract } = deployAdapter(adminKeypair);
+
+      const prepared = await buildTransaction(
+        contract.call('init', xdr.ScVal.scvAddress(admin)),
+        adminKeypair,
+      );
+      const envelope = prepared.toEnvelope();
+      envelope.v1().tx().ext().sorobanData().resources().footprint().readWrite([]);
+
+      const failingTx = await fromEnvelope(envelope);
+      failingTx.sign(adminKeypair);
+      const sendResponse: any = await server.sendTransaction(failingTx);
+      expect(sendResponse.status).toBe('ERROR');
+      expect(sendResponse.diagnosticEvents.length).toBeGreaterThan(0);
+
+      const txResult: any = await server.getTransaction(sendResponse.hash);
+      expect(txResult.status).toBe(rpc.Api.GetTransactionStatus.FAILED);
+      expect(txResult.diagnosticEventsXdr.length).toBeGreaterThan(0);
+      expect(txResult.diagnosticEventsXdr[0]).toBeInstanceOf(xdr.DiagnosticEvent);
+    });
+  });
+
+  describe('Cost Snapshots', () => {
+    it('should compare recorded costs with a snapshot file', async () => {
+      const adminKeypair = createFundedAccount();
+      const admin = new Address(adminKeypair.publicKey()).toScAddress();
+
+      const { contract } = deployAdapter(adminKeypair);
+
+      const initTx = await buildTransaction(
+        contract.call('init', xdr.ScVal.scvAddress(admin)),
+        adminKeypair,
+      );
+      initTx.sign(adminKeypair);
+      await server.sendTransaction(initTx);
+      marsRover.recordCost('init');
+
+      const path = join(mkdtempSync(join(tmpdir(), 'mars-rover-')), 'costs.json');
+      expect(() => marsRover.compareCostSnapshot(path)).toThrow(/writeCostSnapshot/);
+      marsRover.writeCostSnapshot(path);
+      marsRover.compareCostSnapshot(path);
+
+      const snapshot = JSON.parse(readFileSync(path, 'utf8'));
+      expect(snapshot.init.instructions).toBeGreaterThan(0);
+
+      snapshot.init.instructions = Math.floor(snapshot.init.instructions * 0.9);
+      writeFileSync(path, JSON.stringify(snapshot));
+      expect(() => marsRover.compareCostSnapshot(path, 5)).toThrow(/init: instructions/);
+      marsRover.compareCostSnapshot(path, 20);
+
+      snapshot.removed = snapshot.init;
+      writeFileSync(path, JSON.stringify(snapshot));
+      expect(() => marsRover.compareCostSnapshot(path, 20)).toThrow(/removed: not recorded/);
+    });
+  });
+
+  describe('Readable JSON', () => {
+    it('should convert JSON arguments with the contract spec', () => {
+      const owner = createFundedAccount();
+      const { contractAddress } = deployAdapter(owner);
+
+      const init = JSON.parse(
+        marsRover.invoke(contractAddress, 'init', JSON.stringify({ owner: owner.publicKey() }), {
+          authMode: 'mock_all',
+          commit: true,
+        }),
+      );
+      expect(init.error).toBeUndefined();
+
+      const priceData = { price: '42', package_timestamp: 1, write_timestamp: 2 };
+      const check = JSON.parse(
+        marsRover.invoke(contractAddress, 'check_price_data', JSON.stringify([priceData])),
+      );
+      expect(check.error).toContain('Error(Contract, #');
+
+      expect(() =>
+        marsRover.invoke(contractAddress, 'init', JSON.stringify({ ownr: owner.publicKey() })),
+      ).toThrow('Function init has no argument ownr');
+      expect(() =>
+        marsRover.invoke(contractAddress, 'check_price_data', JSON.stringify([{ price: 'x' }])),
+      ).toThrow('Invalid field');
+    });
+
+    it('should decode and encode readable JSON', () => {
+      const keypair = createFundedAccount();
+
+      const amount = nativeToScVal(42, { type: 'i128' });
+      expect(JSON.parse(marsRover.decodeScVal(amount.toXDR('base64')))).toBe('42');
+
+      const encoded = marsRover.encodeScVal(
+        JSON.stringify({ owner: keypair.publicKey(), feeds: ['ETH'], timeout: { u64: 60 } }),
+      );
+      const map = xdr.ScVal.fromXDR(encoded, 'base64').map()!;
+      expect(map.map((entry) => entry.key().sym().toString())).toEqual([
+        'feeds',
+        'owner',
+        'timeout',
+      ]);
+      expect(map[1].val().switch()).toBe(xdr.ScValType.scvAddress());
+      expect(map[2].val().switch()).toBe(xdr.ScValType.scvU64());
+      expect(JSON.parse(marsRover.decodeScVal(encoded))).toEqual({
+        feeds: ['ETH'],
+        owner: keypair.publicKey(),
+        timeout: { u64: '60' },
+      });
+
+      const values = [
+        nativeToScVal(60, { type: 'u64' }),
+        nativeToScVal(-60, { type: 'i64' }),
+        nativeToScVal(60, { type: 'u128' }),
+        xdr.ScVal.scvString('ETH'),
+        xdr.ScVal.scvSymbol('42'),
+        xdr.ScVal.scvMap([
+          new xdr.ScMapEntry({ key: xdr.ScVal.scvU32(1), val: xdr.ScVal.scvSymbol('one') }),
+        ]),
+      ];
+      for (const value of values) {
+        const decoded = marsRover.decodeScVal(value.toXDR('base64'));
+        expect(marsRover.encodeScVal(decoded)).toBe(value.toXDR('base64'));
+      }
+
+      expect(() => marsRover.encodeScVal('{"u32": "x"}')).toThrow();
+    });
   });
 });